@@ -0,0 +1,22 @@
+#version 450
+
+layout(location = 0) in vec3 frag_normal;
+layout(location = 1) in vec4 frag_shadow_position;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Light {
+        vec3 direction;
+        vec3 color;
+} light;
+
+#ifdef SHADOWS
+layout(set = 0, binding = 1) uniform sampler2DShadow shadow_map;
+#endif
+
+void main() {
+        float diffuse = max(dot(normalize(frag_normal), -light.direction), 0.0);
+#ifdef SHADOWS
+        diffuse *= texture(shadow_map, frag_shadow_position.xyz / frag_shadow_position.w);
+#endif
+        f_color = vec4(diffuse * light.color, 1.0);
+}
@@ -0,0 +1,76 @@
+#!/usr/bin/env python3
+"""Write passthrough.spv, the precompiled module expanded in src/main.rs.
+
+glslang only writes one entry point per module, so the module is assembled
+here. It has two entry points that copy a vec4 from location 0 to location 0:
+
+    // vs_main, vertex
+    layout(location = 0) in vec4 position;
+    layout(location = 0) out vec4 v_color;
+    void main() { v_color = position; }
+
+    // fs_main, fragment
+    layout(location = 0) in vec4 v_color;
+    layout(location = 0) out vec4 f_color;
+    void main() { f_color = v_color; }
+
+Run `python3 passthrough.py` from the crate directory to write it again.
+"""
+
+import struct
+
+
+def string(text):
+    data = text.encode() + b"\0"
+    data += b"\0" * (-len(data) % 4)
+    return list(struct.unpack("<%dI" % (len(data) // 4), data))
+
+
+def op(opcode, *operands):
+    words = []
+    for operand in operands:
+        words += operand if isinstance(operand, list) else [operand]
+    return [(len(words) + 1) << 16 | opcode] + words
+
+
+VS_MAIN, FS_MAIN, VOID, FUNCTION, FLOAT, VEC4, INPUT_VEC4, OUTPUT_VEC4 = range(1, 9)
+VS_IN, VS_OUT, FS_IN, FS_OUT, VS_LABEL, FS_LABEL, VS_VALUE, FS_VALUE = range(9, 17)
+BOUND = 17
+
+INPUT, OUTPUT = 1, 3
+
+words = [0x07230203, 0x00010000, 0, BOUND, 0]
+words += op(17, 1)                                                   # OpCapability Shader
+words += op(14, 0, 1)                                                # OpMemoryModel Logical GLSL450
+words += op(15, 0, VS_MAIN, string("vs_main"), VS_IN, VS_OUT)        # OpEntryPoint Vertex
+words += op(15, 4, FS_MAIN, string("fs_main"), FS_IN, FS_OUT)        # OpEntryPoint Fragment
+words += op(16, FS_MAIN, 7)                                          # OpExecutionMode OriginUpperLeft
+words += op(5, VS_MAIN, string("vs_main"))                           # OpName
+words += op(5, FS_MAIN, string("fs_main"))
+words += op(5, VS_IN, string("position"))
+words += op(5, VS_OUT, string("v_color"))
+words += op(5, FS_IN, string("v_color"))
+words += op(5, FS_OUT, string("f_color"))
+for variable in (VS_IN, VS_OUT, FS_IN, FS_OUT):
+    words += op(71, variable, 30, 0)                                 # OpDecorate Location 0
+words += op(19, VOID)                                                # OpTypeVoid
+words += op(33, FUNCTION, VOID)                                      # OpTypeFunction
+words += op(22, FLOAT, 32)                                           # OpTypeFloat 32
+words += op(23, VEC4, FLOAT, 4)                                      # OpTypeVector
+words += op(32, INPUT_VEC4, INPUT, VEC4)                             # OpTypePointer Input
+words += op(32, OUTPUT_VEC4, OUTPUT, VEC4)                           # OpTypePointer Output
+words += op(59, INPUT_VEC4, VS_IN, INPUT)                            # OpVariable
+words += op(59, OUTPUT_VEC4, VS_OUT, OUTPUT)
+words += op(59, INPUT_VEC4, FS_IN, INPUT)
+words += op(59, OUTPUT_VEC4, FS_OUT, OUTPUT)
+for function, label, value, source, destination in ((VS_MAIN, VS_LABEL, VS_VALUE, VS_IN, VS_OUT),
+                                                    (FS_MAIN, FS_LABEL, FS_VALUE, FS_IN, FS_OUT)):
+    words += op(54, VOID, function, 0, FUNCTION)                     # OpFunction
+    words += op(248, label)                                          # OpLabel
+    words += op(61, VEC4, value, source)                             # OpLoad
+    words += op(62, destination, value)                              # OpStore
+    words += op(253)                                                 # OpReturn
+    words += op(56)                                                  # OpFunctionEnd
+
+with open("passthrough.spv", "wb") as f:
+    f.write(struct.pack("<%dI" % len(words), *words))
@@ -1,17 +1,30 @@
-use syn::{Ident, LitStr, LitInt, braced, Token, parenthesized, bracketed};
-use std::collections::{HashSet, HashMap};
+use syn::{Ident, LitBool, LitStr, LitInt, braced, Token, parenthesized, bracketed};
+use std::collections::{BTreeMap, HashMap};
 use syn::parse::{Parse, ParseStream, Result};
+use proc_macro2::Span;
+use crate::spirv::{Spirv, Type, DECORATION_BINDING, DECORATION_BUFFER_BLOCK, DECORATION_DESCRIPTOR_SET, DECORATION_NON_WRITABLE,
+    DIM_1D, DIM_2D, DIM_3D, DIM_BUFFER, DIM_CUBE, DIM_SUBPASS_DATA, IMAGE_SAMPLED_STORAGE, STORAGE_CLASS_STORAGE_BUFFER, STORAGE_CLASS_UNIFORM, STORAGE_CLASS_UNIFORM_CONSTANT};
+use crate::{Stages, duplicate_key, missing_key, unexpected_key};
+use crate::layout::{GlslType, Std, StructDecl, check_block, generate_declared_structs, generate_runtime_array, generate_struct, reflect_struct, runtime_array_layout, std_layout};
 
 pub enum DescriptorType {
-    Buffer(BufferData),
+    // Fields are reflected from the shader when there is no data.
+    Buffer(Option<BufferData>),
+    StorageBuffer(Option<BufferData>),
     SampledImage,
     InputAttachment,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
 }
 
 pub struct BufferData {
     // Ident is the name of the field. String will be either:
-    // float, vec2, vec3, vec4, mat2, mat3, mat4, ... glsl data
-    data: Vec<(Ident, String)>
+    // vec2, vec3, vec4, mat2, mat3, mat4, ... glsl data, an array such as "vec4[8]"
+    // or the name of a struct declared in `structs`.
+    // The struct follows std140 for uniform buffers and std430 for storage buffers.
+    // The last field of a storage buffer can be a runtime sized array, e.g. "vec4[]".
+    data: Vec<(Ident, LitStr)>
 }
 
 impl Parse for BufferData {
@@ -34,10 +47,10 @@ impl Parse for BufferData {
             let data_type: LitStr = in_parens.parse()?;
 
             if !in_parens.is_empty() {
-                panic!("Expected only tuple here");
+                return Err(in_parens.error("expected only (name, \"type\")"));
             }
 
-            data.push((ident, data_type.value()));
+            data.push((ident, data_type));
 
             if !in_brackets.is_empty() {
                 in_brackets.parse::<Token![,]>()?;
@@ -51,22 +64,32 @@ impl Parse for BufferData {
 
 }
 
-/// This is parsed from the macro input
+/// This is parsed from the macro input. It has to match a descriptor of the shader.
 pub struct DescriptorInput {
     name: Ident,
     ty: DescriptorType,
     binding: usize,
     set: usize,
+    // Required for unsized arrays such as `uniform sampler2D textures[];`
+    array_count: Option<u32>,
+    // Buffer bound with dynamic offsets.
+    dynamic: bool,
+    // Stages that use the descriptor. Only the current stage by default.
+    stages: Option<Stages>,
+    // Structs that can be used in the data.
+    structs: Vec<StructDecl>,
 }
 
 impl Parse for DescriptorInput {
 
     /// `{
     ///     ty: Buffer,
-    ///     data: [(model, "vec3"), ...]
+    ///     data: [(lights, "Light[8]"), (count, "int")],
+    ///     structs: { Light: [(position, "vec3"), (color, "vec3")] },
     /// }`
     fn parse(input: ParseStream) -> Result<Self> {
 
+        let span = input.cursor().span();
         let in_braces;
         braced!(in_braces in input);
 
@@ -76,6 +99,10 @@ impl Parse for DescriptorInput {
         let mut binding = None;
         let mut name = None;
         let mut set = None;
+        let mut array_count = None;
+        let mut dynamic = None;
+        let mut stages = None;
+        let mut structs = None;
         while !in_braces.is_empty() {
 
             let key: Ident = in_braces.parse()?;
@@ -84,39 +111,81 @@ impl Parse for DescriptorInput {
             match key.to_string().as_str() {
                 "name" => {
                     if name.is_some() {
-                        panic!("Cannot define 'name' twice");
+                        return Err(duplicate_key(&key));
                     }
 
                     name = Some(in_braces.parse::<Ident>()?);
                 },
                 "ty" => {
                     if ty_str.is_some() {
-                        panic!("Cannot define 'ty' twice");
+                        return Err(duplicate_key(&key));
                     }
 
                     ty_str = Some(in_braces.parse::<Ident>()?);
                 },
                 "data" => {
                     if data.is_some() {
-                        panic!("Cannot define 'data' twice");
+                        return Err(duplicate_key(&key));
                     }
 
                     data = Some(in_braces.parse::<BufferData>()?);
                 },
                 "binding" => {
                     if binding.is_some() {
-                        panic!("Cannot define 'binding' twice");
+                        return Err(duplicate_key(&key));
                     }
 
                     binding = Some(in_braces.parse::<LitInt>()?.value() as usize);
                 },
                 "set" => {
                     if set.is_some() {
-                        panic!("Cannot define 'set' twice");
+                        return Err(duplicate_key(&key));
                     }
                     set = Some(in_braces.parse::<LitInt>()?.value() as usize);
                 },
-                _ => panic!("Not expected"),
+                "array_count" => {
+                    if array_count.is_some() {
+                        return Err(duplicate_key(&key));
+                    }
+                    array_count = Some(in_braces.parse::<LitInt>()?.value() as u32);
+                },
+                "dynamic" => {
+                    if dynamic.is_some() {
+                        return Err(duplicate_key(&key));
+                    }
+                    dynamic = Some(in_braces.parse::<LitBool>()?.value);
+                },
+                "stages" => {
+                    if stages.is_some() {
+                        return Err(duplicate_key(&key));
+                    }
+                    stages = Some(in_braces.parse::<Stages>()?);
+                },
+                "structs" => {
+                    if structs.is_some() {
+                        return Err(duplicate_key(&key));
+                    }
+
+                    // Parse { Light: [(position, "vec3"), ...], ... }
+                    let mut decls = vec![];
+                    let in_struct_braces;
+                    braced!(in_struct_braces in in_braces);
+                    while !in_struct_braces.is_empty() {
+                        let struct_name: Ident = in_struct_braces.parse()?;
+                        in_struct_braces.parse::<Token![:]>()?;
+                        let fields = in_struct_braces.parse::<BufferData>()?.data.into_iter()
+                            .map(|(field, ty)| (field, ty.value()))
+                            .collect();
+                        decls.push(StructDecl { name: struct_name, fields });
+
+                        if !in_struct_braces.is_empty() {
+                            in_struct_braces.parse::<Token![,]>()?;
+                        }
+                    }
+
+                    structs = Some(decls);
+                },
+                _ => return Err(unexpected_key(&key, &["name", "ty", "data", "structs", "binding", "set", "array_count", "dynamic", "stages"])),
             }
 
             if !in_braces.is_empty() {
@@ -124,91 +193,501 @@ impl Parse for DescriptorInput {
             }
         }
 
-        let ty = match ty_str.expect("Could not find uniform type").to_string().as_ref() {
-            "Buffer" => DescriptorType::Buffer(data.expect("Could not find uniform buffer data")),
+        let ty_str = ty_str.ok_or_else(|| missing_key(span, "ty"))?;
+        let structs = structs.unwrap_or_default();
+        if let Some(BufferData { data }) = &data {
+            // The field types are checked here so the error points at the type.
+            for (index, (field, ty)) in data.iter().enumerate() {
+                let value = ty.value();
+                let element = if value.ends_with("[]") {
+                    if ty_str != "StorageBuffer" || index != data.len() - 1 {
+                        return Err(syn::Error::new_spanned(ty, format!(
+                            "runtime sized array `{}` has to be the last field of a storage buffer", field)));
+                    }
+
+                    &value[..value.len() - 2]
+                } else {
+                    &value[..]
+                };
+
+                if GlslType::parse(element, &structs).is_none() {
+                    let declared: Vec<_> = structs.iter().map(|decl| decl.name.to_string()).collect();
+                    return Err(syn::Error::new_spanned(ty, format!(
+                        "unsupported type `{}` for field `{}`, expected a GLSL scalar, vector, matrix or array type, or a struct declared in `structs` ({})",
+                        value, field, declared.join(", "))));
+                }
+            }
+        }
+
+        let ty = match ty_str.to_string().as_ref() {
+            "Buffer" => DescriptorType::Buffer(data),
+            "StorageBuffer" => DescriptorType::StorageBuffer(data),
+            "SampledImage" | "InputAttachment" | "StorageImage" | "UniformTexelBuffer" | "StorageTexelBuffer" if data.is_some() => {
+                return Err(syn::Error::new_spanned(&ty_str, format!("`data` cannot be used with a {} descriptor", ty_str)));
+            },
             "SampledImage" => DescriptorType::SampledImage,
             "InputAttachment" => DescriptorType::InputAttachment,
-            _ => panic!("Descriptor type not supported"),
+            "StorageImage" => DescriptorType::StorageImage,
+            "UniformTexelBuffer" => DescriptorType::UniformTexelBuffer,
+            "StorageTexelBuffer" => DescriptorType::StorageTexelBuffer,
+            _ => return Err(syn::Error::new_spanned(&ty_str, format!(
+                "unknown descriptor type `{}`, expected one of: Buffer, StorageBuffer, SampledImage, InputAttachment, StorageImage, UniformTexelBuffer, StorageTexelBuffer",
+                ty_str))),
         };
 
+        let dynamic = dynamic.unwrap_or(false);
+        match ty {
+            DescriptorType::Buffer(_) | DescriptorType::StorageBuffer(_) => (),
+            _ if dynamic => {
+                return Err(syn::Error::new_spanned(&ty_str, format!("a {} descriptor cannot be dynamic, only buffers can", ty_str)));
+            },
+            _ => (),
+        }
+
         Ok(Self {
-            name: name.expect("Could not find descriptor name"),
+            name: name.ok_or_else(|| missing_key(span, "name"))?,
             ty,
-            binding: binding.expect("could not find descriptor binding"),
-            set: set.expect("Could not find descriptor set"),
+            binding: binding.ok_or_else(|| missing_key(span, "binding"))?,
+            set: set.ok_or_else(|| missing_key(span, "set"))?,
+            array_count,
+            dynamic,
+            stages,
+            structs,
         })
     }
 }
 
 
-pub fn generate_descriptor_layout(descriptor_inputs: Vec<DescriptorInput>) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+/// Kind of descriptor found in the compiled shader.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReflectedKind {
+    UniformBuffer,
+    StorageBuffer,
+    CombinedImageSampler,
+    InputAttachment,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+}
 
-    // first let's order by set and binding.
-    // I'm tired, don't judge.
-    let mut bindings_per_set = HashMap::new();
-    let mut highest_set = 0;
+struct ReflectedDescriptor {
+    set: usize,
+    binding: usize,
+    kind: ReflectedKind,
+    ty: Type,
+    readonly: bool,
+    // None for unsized arrays.
+    array_count: Option<u32>,
+    // Only known from the macro input.
+    dynamic: bool,
+    stages: Option<proc_macro2::TokenStream>,
+    // The errors about a descriptor given in the macro point at its name.
+    span: Span,
+}
+
+/// Every variable with a `DescriptorSet` and `Binding` decoration is a descriptor.
+fn reflect_descriptors(spirv: &Spirv) -> Result<Vec<ReflectedDescriptor>> {
+
+    let mut descriptors = vec![];
+    for id in spirv.variables() {
+
+        let set = spirv.decoration(id, DECORATION_DESCRIPTOR_SET);
+        let binding = spirv.decoration(id, DECORATION_BINDING);
+        let (set, binding) = match (set, binding) {
+            (Some(set), Some(binding)) => (set[0] as usize, binding[0] as usize),
+            _ => continue,
+        };
+
+        let name = spirv.name(id).to_owned();
+        let (storage_class, type_id) = spirv.variable(id).ok_or_else(|| {
+            syn::Error::new(Span::call_site(), format!("cannot find the type of the descriptor {}", name))
+        })?;
+
+        // Arrays of descriptors, e.g. `uniform sampler2D textures[16];`
+        let (ty, array_count) = match spirv.resolve_type(type_id) {
+            Type::Array { element, length, .. } => (*element, Some(length)),
+            Type::RuntimeArray { element, .. } => (*element, None),
+            ty => (ty, Some(1)),
+        };
+
+        let kind = match (storage_class, &ty) {
+            // Before SPIR-V 1.3, storage buffers are uniforms with a BufferBlock decoration.
+            (STORAGE_CLASS_UNIFORM, Type::Struct { id, .. }) => {
+                if spirv.decoration(*id, DECORATION_BUFFER_BLOCK).is_some() {
+                    ReflectedKind::StorageBuffer
+                } else {
+                    ReflectedKind::UniformBuffer
+                }
+            },
+            (STORAGE_CLASS_STORAGE_BUFFER, Type::Struct { .. }) => ReflectedKind::StorageBuffer,
+            (STORAGE_CLASS_UNIFORM_CONSTANT, Type::SampledImage { image }) => {
+                match **image {
+                    // samplerBuffer
+                    Type::Image { dim: DIM_BUFFER, .. } => ReflectedKind::UniformTexelBuffer,
+                    _ => ReflectedKind::CombinedImageSampler,
+                }
+            },
+            (STORAGE_CLASS_UNIFORM_CONSTANT, &Type::Image { dim: DIM_SUBPASS_DATA, .. }) => ReflectedKind::InputAttachment,
+            // imageBuffer
+            (STORAGE_CLASS_UNIFORM_CONSTANT, &Type::Image { dim: DIM_BUFFER, sampled: IMAGE_SAMPLED_STORAGE, .. }) => {
+                ReflectedKind::StorageTexelBuffer
+            },
+            (STORAGE_CLASS_UNIFORM_CONSTANT, &Type::Image { sampled: IMAGE_SAMPLED_STORAGE, .. }) => ReflectedKind::StorageImage,
+            _ => return Err(syn::Error::new(Span::call_site(), format!(
+                "descriptor {} (set {}, binding {}) of the shader is not supported yet", name, set, binding))),
+        };
+
+        // `readonly` buffers have all their members decorated with NonWritable.
+        let readonly = match (kind, &ty) {
+            (ReflectedKind::StorageBuffer, Type::Struct { id: struct_id, members }) => {
+                spirv.decoration(id, DECORATION_NON_WRITABLE).is_some() ||
+                    (0..members.len() as u32).all(|m| spirv.member_decoration(*struct_id, m, DECORATION_NON_WRITABLE).is_some())
+            },
+            (ReflectedKind::StorageImage, _) | (ReflectedKind::StorageTexelBuffer, _) => {
+                spirv.decoration(id, DECORATION_NON_WRITABLE).is_some()
+            },
+            _ => true,
+        };
+
+        descriptors.push(ReflectedDescriptor {
+            set, binding, kind, ty, readonly, array_count,
+            dynamic: false,
+            stages: None,
+            span: Span::call_site(),
+        });
+    }
+
+    descriptors.sort_by_key(|d| (d.set, d.binding));
+    Ok(descriptors)
+}
+
+fn image_dimensions(dim: u32, span: Span) -> Result<proc_macro2::TokenStream> {
+    match dim {
+        DIM_1D => Ok(quote!(DescriptorImageDescDimensions::OneDimensional)),
+        DIM_2D => Ok(quote!(DescriptorImageDescDimensions::TwoDimensional)),
+        DIM_3D => Ok(quote!(DescriptorImageDescDimensions::ThreeDimensional)),
+        DIM_CUBE => Ok(quote!(DescriptorImageDescDimensions::Cube)),
+        _ => Err(syn::Error::new(span, format!("image dimension {} is not supported yet", dim))),
+    }
+}
+
+/// With dynamic offsets, each element of the buffer has to start at a multiple
+/// of the minimum offset alignment of the device.
+fn generate_dynamic_stride(name: &Ident, kind: ReflectedKind) -> proc_macro2::TokenStream {
+    let limit = match kind {
+        ReflectedKind::StorageBuffer => quote!(min_storage_buffer_offset_alignment),
+        _ => quote!(min_uniform_buffer_offset_alignment),
+    };
+
+    quote!(
+        impl #name {
+            /// Distance in bytes between two elements of a buffer bound with dynamic offsets.
+            pub fn dynamic_stride(device: &vulkano::device::Device) -> usize {
+                let alignment = device.physical_device().limits().#limit() as usize;
+                let size = ::std::mem::size_of::<#name>();
+                (size + alignment - 1) / alignment * alignment
+            }
+        }
+    )
+}
+
+fn image_array_layers(arrayed: bool) -> proc_macro2::TokenStream {
+    if arrayed {
+        quote!(DescriptorImageDescArray::Arrayed { max_layers: None })
+    } else {
+        quote!(DescriptorImageDescArray::NonArrayed)
+    }
+}
+
+/// Dimensions, layers and samples come from the GLSL type, for example a
+/// `sampler2DArray` is a two dimensional arrayed image.
+fn image_desc(ty: &Type, sampled: bool, span: Span) -> Result<proc_macro2::TokenStream> {
+    let (dim, arrayed, multisampled, format) = match *ty {
+        Type::Image { dim, arrayed, multisampled, format, .. } => (dim, arrayed, multisampled, format),
+        _ => unreachable!("Only images are given to image_desc"),
+    };
+
+    let dimensions = image_dimensions(dim, span)?;
+    let format = image_format(format, span)?;
+    let array_layers = image_array_layers(arrayed);
+    Ok(quote!(
+        DescriptorImageDesc {
+            sampled: #sampled,
+            dimensions: #dimensions,
+            format: #format,
+            multisampled: #multisampled,
+            array_layers: #array_layers,
+        }
+    ))
+}
+
+/// Format given with `layout(rgba8)` and friends. Storage images can omit it
+/// when the `shaderStorageImageWriteWithoutFormat` feature is enabled.
+fn image_format(format: u32, span: Span) -> Result<proc_macro2::TokenStream> {
+    let vulkan_format = match format {
+        0 => return Ok(quote!(None)),
+        1 => "R32G32B32A32Sfloat",
+        2 => "R16G16B16A16Sfloat",
+        3 => "R32Sfloat",
+        4 => "R8G8B8A8Unorm",
+        5 => "R8G8B8A8Snorm",
+        6 => "R32G32Sfloat",
+        7 => "R16G16Sfloat",
+        8 => "B10G11R11UfloatPack32",
+        9 => "R16Sfloat",
+        10 => "R16G16B16A16Unorm",
+        11 => "A2B10G10R10UnormPack32",
+        12 => "R16G16Unorm",
+        13 => "R8G8Unorm",
+        14 => "R16Unorm",
+        15 => "R8Unorm",
+        16 => "R16G16B16A16Snorm",
+        17 => "R16G16Snorm",
+        18 => "R8G8Snorm",
+        19 => "R16Snorm",
+        20 => "R8Snorm",
+        21 => "R32G32B32A32Sint",
+        22 => "R16G16B16A16Sint",
+        23 => "R8G8B8A8Sint",
+        24 => "R32Sint",
+        25 => "R32G32Sint",
+        26 => "R16G16Sint",
+        27 => "R8G8Sint",
+        28 => "R16Sint",
+        29 => "R8Sint",
+        30 => "R32G32B32A32Uint",
+        31 => "R16G16B16A16Uint",
+        32 => "R8G8B8A8Uint",
+        33 => "R32Uint",
+        34 => "A2B10G10R10UintPack32",
+        35 => "R32G32Uint",
+        36 => "R16G16Uint",
+        37 => "R8G8Uint",
+        38 => "R16Uint",
+        39 => "R8Uint",
+        _ => return Err(syn::Error::new(span, format!("image format {} is not supported yet", format))),
+    };
+
+    let vulkan_format = Ident::new(vulkan_format, Span::call_site());
+    Ok(quote!(Some(Format::#vulkan_format)))
+}
+
+/// A descriptor cannot have the name, or the set and binding, of one defined before.
+pub fn check_unique(previous: &[DescriptorInput], desc: &DescriptorInput) -> Result<()> {
+    for other in previous {
+        if other.name == desc.name {
+            return Err(syn::Error::new_spanned(&desc.name, format!("descriptor `{}` is defined twice", desc.name)));
+        }
+
+        if other.set == desc.set && other.binding == desc.binding {
+            return Err(syn::Error::new_spanned(&desc.name, format!("descriptor {} has the same set {} and binding {} as {}",
+                                                                   desc.name, desc.set, desc.binding, other.name)));
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the shader has a descriptor at the set and binding of `desc`.
+pub fn is_used(desc: &DescriptorInput, spirv: &Spirv) -> bool {
+    spirv.variables().into_iter().any(|id| {
+        spirv.decoration(id, DECORATION_DESCRIPTOR_SET).map(|params| params[0] as usize) == Some(desc.set)
+            && spirv.decoration(id, DECORATION_BINDING).map(|params| params[0] as usize) == Some(desc.binding)
+    })
+}
+
+/// `used_by` is the shader, or all its variants.
+pub fn unused_error(desc: &DescriptorInput, used_by: &str) -> syn::Error {
+    syn::Error::new_spanned(&desc.name, format!(
+        "descriptor {} (set {}, binding {}) is not used by {}", desc.name, desc.set, desc.binding, used_by))
+}
+
+/// The pipeline layout comes from the descriptors found in the SPIR-V. The descriptors
+/// given in the macro are checked against it and are used to name the generated structs.
+/// The errors about them point at their name. With `allow_unused`, the ones
+/// that are not in the shader are skipped.
+pub fn generate_descriptor_layout(descriptor_inputs: &[DescriptorInput], spirv: &Spirv, allow_unused: bool)
+    -> Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+
+    let mut reflected = reflect_descriptors(spirv)?;
+
+    let mut descriptor_structs = vec![];
+    let mut declared_structs = BTreeMap::new();
     for desc in descriptor_inputs.iter() {
+        let found = match reflected.iter_mut().find(|r| r.set == desc.set && r.binding == desc.binding) {
+            Some(found) => found,
+            None if allow_unused => continue,
+            None => return Err(unused_error(desc, "the shader")),
+        };
+        found.span = desc.name.span();
+
+        let matching = match desc.ty {
+            DescriptorType::Buffer(_) => found.kind == ReflectedKind::UniformBuffer,
+            DescriptorType::StorageBuffer(_) => found.kind == ReflectedKind::StorageBuffer,
+            DescriptorType::SampledImage => found.kind == ReflectedKind::CombinedImageSampler,
+            DescriptorType::InputAttachment => found.kind == ReflectedKind::InputAttachment,
+            DescriptorType::StorageImage => found.kind == ReflectedKind::StorageImage,
+            DescriptorType::UniformTexelBuffer => found.kind == ReflectedKind::UniformTexelBuffer,
+            DescriptorType::StorageTexelBuffer => found.kind == ReflectedKind::StorageTexelBuffer,
+        };
 
-        if desc.set > highest_set {
-            highest_set = desc.set;
+        if !matching {
+            return Err(syn::Error::new_spanned(&desc.name, format!(
+                "descriptor {} (set {}, binding {}) is a {:?} in the shader", desc.name, desc.set, desc.binding, found.kind)));
         }
-        if !bindings_per_set.contains_key(&desc.set) {
-            bindings_per_set.insert(desc.set, HashSet::new());
+
+        match (desc.array_count, found.array_count) {
+            (Some(count), Some(reflected_count)) if count != reflected_count => {
+                return Err(syn::Error::new_spanned(&desc.name, format!(
+                    "descriptor {} has {} elements in the shader, not {}", desc.name, reflected_count, count)));
+            },
+            (Some(count), None) => found.array_count = Some(count),
+            _ => (),
+        }
+
+        // Only buffers can be dynamic, this is checked when parsing.
+        found.dynamic = desc.dynamic;
+
+        found.stages = desc.stages.as_ref().map(|stages| stages.generate_shaderstages());
+
+        let name = &desc.name;
+        match &desc.ty {
+            DescriptorType::Buffer(Some(BufferData { data })) | DescriptorType::StorageBuffer(Some(BufferData { data })) => {
+                let std = if found.kind == ReflectedKind::StorageBuffer { Std::Std430 } else { Std::Std140 };
+
+                let mut members = vec![];
+                let mut runtime_array = None;
+                // The types and the position of the runtime sized array are
+                // checked when parsing. The declared structs can be renamed
+                // when they are also used with the other layout.
+                for (field_name, field_ty) in data.iter() {
+                    let field_ty = field_ty.value();
+                    if field_ty.ends_with("[]") {
+                        let element = &field_ty[..field_ty.len() - 2];
+                        let element = GlslType::parse(element, &desc.structs)
+                            .expect("The field types are checked when parsing");
+                        runtime_array = Some(generate_declared_structs(&element, std, &mut declared_structs));
+                        continue;
+                    }
+
+                    let ty = GlslType::parse(&field_ty, &desc.structs)
+                        .expect("The field types are checked when parsing");
+                    members.push((field_name.clone(), generate_declared_structs(&ty, std, &mut declared_structs)));
+                }
+
+                // The offsets from the std140/std430 rules and the types have to
+                // be the same as what the GLSL compiler decided.
+                let array_field = runtime_array.as_ref().map(|element| (&data[data.len() - 1].0, element));
+                check_block(spirv, &found.ty, name, &members, array_field, std)?;
+
+                let (fields, size) = std_layout(&members, std);
+                let array_layout = runtime_array.as_ref().map(|element| runtime_array_layout(&fields, element, std));
+
+                descriptor_structs.push(match (runtime_array, array_layout) {
+                    (Some(element), Some((offset, stride))) => generate_runtime_array(name, &fields, offset, &element.field_type(std), stride),
+                    _ => generate_struct(name, &fields, size),
+                });
+            },
+            // Without data, the struct is the one declared in the shader. The
+            // nested structs can be shared with other blocks.
+            DescriptorType::Buffer(None) | DescriptorType::StorageBuffer(None) => {
+                let std = if found.kind == ReflectedKind::StorageBuffer { Std::Std430 } else { Std::Std140 };
+                reflect_struct(spirv, &found.ty, name, std, &mut declared_structs)?;
+            },
+            _ => (),
         }
 
-        bindings_per_set.get_mut(&desc.set)
-            .expect(&format!("Cannot find HashSet for set: {}", desc.set))
-            .insert(desc.binding);
+        if desc.dynamic {
+            descriptor_structs.push(generate_dynamic_stride(name, found.kind));
+        }
     }
 
-    let num_set = highest_set+1;
+    // Bindings do not have to be contiguous, so the number of bindings in a set is
+    // the highest binding + 1.
+    let mut bindings_per_set = HashMap::new();
+    let mut num_set = 0;
+    for desc in reflected.iter() {
+        num_set = num_set.max(desc.set + 1);
+        let num_bindings = bindings_per_set.entry(desc.set).or_insert(0);
+        *num_bindings = (*num_bindings).max(desc.binding + 1);
+    }
 
     let mut num_bindings = vec![];
     for set in 0..num_set {
-        num_bindings.push(if let Some(ref bindings) = &bindings_per_set.get(&set) {
-           let binding_length = bindings.len();
-           quote!(
-                #set => Some(#binding_length),
-               )
-        } else {
-            quote!(
-                #set => Some(0),
-                )
-        });
+        let binding_length = bindings_per_set.get(&set).cloned().unwrap_or(0);
+        num_bindings.push(quote!(
+            #set => Some(#binding_length),
+        ));
     }
 
     let mut descriptor_desc = vec![];
-    let mut descriptor_structs = vec![];
-    for desc in descriptor_inputs.iter() {
+    for desc in reflected.iter() {
 
         let set = desc.set;
         let binding = desc.binding;
+        let readonly = desc.readonly;
+        let dynamic = desc.dynamic;
+        let stages = desc.stages.clone().unwrap_or(quote!(self.0.clone()));
+        let array_count = match desc.array_count {
+            Some(array_count) => array_count,
+            None => return Err(syn::Error::new(desc.span, format!(
+                "descriptor at set {}, binding {} is an unsized array, its size has to be given with `array_count`", set, binding))),
+        };
 
-        let ty = match &desc.ty {
-            DescriptorType::Buffer(_) => {
+        let ty = match desc.kind {
+            ReflectedKind::UniformBuffer | ReflectedKind::StorageBuffer => {
+                let storage = desc.kind == ReflectedKind::StorageBuffer;
                 quote!(
                     DescriptorDescTy::Buffer(DescriptorBufferDesc {
-                        dynamic: Some(false),
-                        storage: false,
+                        dynamic: Some(#dynamic),
+                        storage: #storage,
                     })
                 )
             },
-            DescriptorType::SampledImage => {
+            ReflectedKind::CombinedImageSampler => {
+                let image_desc = match &desc.ty {
+                    Type::SampledImage { image } => image_desc(image, true, desc.span)?,
+                    _ => unreachable!(),
+                };
                 quote!(
-                    DescriptorDescTy::CombinedImageSampler(DescriptorImageDesc {
-                        sampled: true,
-                        dimensions: DescriptorImageDescDimensions::TwoDimensional,
-                        format: None,
-                        multisampled: false,
-                        array_layers: DescriptorImageDescArray::NonArrayed,
-                    })
+                    DescriptorDescTy::CombinedImageSampler(#image_desc)
                 )
             },
-            DescriptorType::InputAttachment => {
+            ReflectedKind::InputAttachment => {
+                let (multisampled, array_layers) = match desc.ty {
+                    Type::Image { arrayed, multisampled, .. } => (multisampled, image_array_layers(arrayed)),
+                    _ => unreachable!(),
+                };
                 quote!(
                     DescriptorDescTy::InputAttachment {
-                        multisampled: false,
-                        array_layers: DescriptorImageDescArray::NonArrayed,
+                        multisampled: #multisampled,
+                        array_layers: #array_layers,
+                    }
+                )
+            },
+            ReflectedKind::StorageImage => {
+                let image_desc = image_desc(&desc.ty, false, desc.span)?;
+                quote!(
+                    DescriptorDescTy::Image(#image_desc)
+                )
+            },
+            ReflectedKind::UniformTexelBuffer => {
+                quote!(
+                    DescriptorDescTy::TexelBuffer {
+                        storage: false,
+                        format: None,
+                    }
+                )
+            },
+            ReflectedKind::StorageTexelBuffer => {
+                let format = match desc.ty {
+                    Type::Image { format, .. } => image_format(format, desc.span)?,
+                    _ => unreachable!(),
+                };
+                quote!(
+                    DescriptorDescTy::TexelBuffer {
+                        storage: true,
+                        format: #format,
                     }
                 )
             },
@@ -217,66 +696,15 @@ pub fn generate_descriptor_layout(descriptor_inputs: Vec<DescriptorInput>) -> (p
 
                 (#set, #binding) => Some(DescriptorDesc {
                     ty: #ty,
-                    array_count: 1u32,
-                    stages: self.0.clone(),
-                    readonly: true,
+                    array_count: #array_count,
+                    stages: #stages,
+                    readonly: #readonly,
                 }),
                 ));
-
-        let name = &desc.name;
-        let mut fields = vec![];
-        if let DescriptorType::Buffer(BufferData {data}) = &desc.ty {
-            for (field_name, field_ty) in data {
-                // ident and string.
-                match field_ty.as_ref() {
-                    "vec2" => {
-                        fields.push(quote!(
-                                pub #field_name: [f32; 2],
-                                ));
-                    },
-                    "vec3" => {
-                        fields.push(quote!(
-                                pub #field_name: [f32; 3],
-                                ));
-                    },
-                    "vec4" => {
-                        fields.push(quote!(
-                                pub #field_name: [f32; 4],
-                                ));
-                    },
-                    "mat2" => {
-                        fields.push(quote!(
-                                pub #field_name: [[f32; 2]; 2],
-                                ));
-                    },
-                    "mat3" => {
-                        fields.push(quote!(
-                                pub #field_name: [[f32; 3]; 3],
-                                ));
-                    },
-                    "mat4" => {
-                        fields.push(quote!(
-                                pub #field_name: [[f32; 4]; 4],
-                                ));
-                    },
-                    x => panic!(format!("Uniform field type {} not supported yet", x)),
-                }
-            }
-        }
-
-        if !fields.is_empty() {
-            descriptor_structs.push(quote!(
-                    #[repr(C)]
-                    #[derive(Debug, Clone, Copy)]
-                    pub struct #name {
-                        #( #fields )*
-                    }
-            ));
-        }
     }
 
-
-    (quote!(
+    let declared_structs = declared_structs.values();
+    Ok((quote!(
 
             fn num_sets(&self) -> usize {
                 #num_set
@@ -295,6 +723,6 @@ pub fn generate_descriptor_layout(descriptor_inputs: Vec<DescriptorInput>) -> (p
                     _ => None,
                 }
             }
-    ), quote!(#( #descriptor_structs )*))
+    ), quote!(#( #descriptor_structs )* #( #declared_structs )*)))
 }
 
@@ -0,0 +1,169 @@
+//! Errors and warnings of the GLSL compiler. They are reported on the `path`
+//! or `src` of the macro input instead of making the macro panic.
+
+use syn::LitStr;
+use std::fs;
+
+/// One message of glslang, for example
+/// `shaders/light.frag:12: error: 'foo' : undeclared identifier`.
+pub struct Diagnostic {
+    file: String,
+    line: Option<usize>,
+    severity: &'static str,
+    message: String,
+    // The GLSL when it is not in a file, e.g. inline source.
+    source: Option<String>,
+}
+
+impl Diagnostic {
+
+    /// A message that does not come from glslang, e.g. the file cannot be read.
+    pub fn new(file: &str, message: String) -> Self {
+        Diagnostic {
+            file: file.to_owned(),
+            line: None,
+            severity: "error",
+            message,
+            source: None,
+        }
+    }
+
+    /// Use `source` for the lines of `file` instead of reading it from disk.
+    pub fn set_source(&mut self, file: &str, source: &str) {
+        if self.file == file {
+            self.source = Some(source.to_owned());
+        }
+    }
+
+    /// The message followed by the line of GLSL it is about.
+    fn render(&self) -> String {
+        let line = match self.line {
+            Some(line) => line,
+            None => return format!("{}: {}: {}", self.file, self.severity, self.message),
+        };
+
+        let mut rendered = format!("{}:{}: {}: {}", self.file, line, self.severity, self.message);
+        let source = match self.source {
+            Some(ref source) => Some(source.clone()),
+            None => fs::read_to_string(&self.file).ok(),
+        };
+        let source_line = source
+            .and_then(|source| source.lines().nth(line.saturating_sub(1)).map(|l| l.trim_end().to_owned()));
+        if let Some(source_line) = source_line {
+            rendered.push_str(&format!("\n{} | {}", line, source_line));
+        }
+
+        rendered
+    }
+}
+
+/// Split the output of shaderc in one diagnostic per message. The summary
+/// lines such as `1 error generated.` are skipped.
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for line in output.lines() {
+        let (index, severity) = match (line.find(": error: "), line.find(": warning: ")) {
+            (Some(index), _) => (index, "error"),
+            (None, Some(index)) => (index, "warning"),
+            (None, None) => continue,
+        };
+
+        // The file name can contain ':' on Windows, so the line number is
+        // what comes after the last one.
+        let location = &line[..index];
+        let (file, line_number) = match location.rfind(':') {
+            Some(colon) => match location[colon + 1..].parse::<usize>() {
+                Ok(line_number) => (&location[..colon], Some(line_number)),
+                Err(_) => (location, None),
+            },
+            None => (location, None),
+        };
+
+        diagnostics.push(Diagnostic {
+            file: file.to_owned(),
+            line: line_number,
+            severity,
+            message: line[index + severity.len() + 4..].trim().to_owned(),
+            source: None,
+        });
+    }
+
+    diagnostics
+}
+
+/// One `compile_error!` per diagnostic, pointing at the path of the shader.
+pub fn generate_errors(path: &LitStr, diagnostics: &[Diagnostic]) -> proc_macro2::TokenStream {
+    let errors = diagnostics.iter().map(|diagnostic| {
+        let message = diagnostic.render();
+        quote_spanned!(path.span()=> compile_error!(#message);)
+    });
+
+    quote!(#( #errors )*)
+}
+
+/// Proc macros cannot emit warnings, but using a deprecated item does. Each
+/// warning becomes a deprecated constant that is used right away.
+pub fn generate_warnings(path: &LitStr, diagnostics: &[Diagnostic]) -> proc_macro2::TokenStream {
+    let warnings = diagnostics.iter().enumerate().map(|(index, diagnostic)| {
+        let message = diagnostic.render();
+        let warning = syn::Ident::new(&format!("glsl_warning_{}", index), path.span());
+        let usage = syn::Ident::new(&format!("_GLSL_WARNING_{}", index), path.span());
+        quote_spanned!(path.span()=>
+            #[deprecated(note = #message)]
+            #[allow(non_upper_case_globals)]
+            const #warning: () = ();
+            #[allow(dead_code)]
+            const #usage: () = #warning;
+        )
+    });
+
+    quote!(#( #warnings )*)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_errors_and_warnings() {
+        let output = "shaders/light.frag:12: error: 'foo' : undeclared identifier\n\
+                      shaders/light.frag:3: warning: '#extension' : extension not supported\n\
+                      1 error generated.\n";
+        let diagnostics = parse_diagnostics(output);
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(diagnostics[0].file, "shaders/light.frag");
+        assert_eq!(diagnostics[0].line, Some(12));
+        assert_eq!(diagnostics[0].severity, "error");
+        assert_eq!(diagnostics[0].message, "'foo' : undeclared identifier");
+
+        assert_eq!(diagnostics[1].line, Some(3));
+        assert_eq!(diagnostics[1].severity, "warning");
+        assert_eq!(diagnostics[1].message, "'#extension' : extension not supported");
+    }
+
+    #[test]
+    fn parse_windows_paths() {
+        let diagnostics = parse_diagnostics("C:\\shaders\\light.frag:7: error: syntax error");
+        assert_eq!(diagnostics[0].file, "C:\\shaders\\light.frag");
+        assert_eq!(diagnostics[0].line, Some(7));
+    }
+
+    #[test]
+    fn parse_without_line() {
+        let diagnostics = parse_diagnostics("light.frag: error: #version: versions above 450 are not supported");
+        assert_eq!(diagnostics[0].file, "light.frag");
+        assert_eq!(diagnostics[0].line, None);
+        assert_eq!(diagnostics[0].message, "#version: versions above 450 are not supported");
+    }
+
+    #[test]
+    fn render_with_inline_source() {
+        let mut diagnostic = parse_diagnostics("shader.frag:2: error: 'x' : undeclared identifier").remove(0);
+        diagnostic.set_source("shader.frag", "#version 450\nvoid main() { x; }\n");
+        assert_eq!(diagnostic.render(), "shader.frag:2: error: 'x' : undeclared identifier\n2 | void main() { x; }");
+
+        let diagnostic = Diagnostic::new("missing.frag", "cannot read the shader".to_owned());
+        assert_eq!(diagnostic.render(), "missing.frag: error: cannot read the shader");
+    }
+}
@@ -0,0 +1,262 @@
+use syn::{Ident, LitStr, braced, Token};
+use syn::parse::{Parse, ParseStream, Result};
+use proc_macro2::Span;
+use crate::{duplicate_key, missing_key, unexpected_key};
+use crate::spirv::{Spirv, Type, DECORATION_BUILTIN, DECORATION_LOCATION, DECORATION_PATCH};
+
+/// Manual description of an input or output of the shader. When these
+/// are given in the macro, they replace what is found in the SPIR-V.
+pub struct InterfaceElement {
+    format: Ident,
+    name: LitStr,
+}
+
+
+impl Parse for InterfaceElement {
+
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut format = None;
+        let mut name = None;
+        let span = input.cursor().span();
+        let in_braces;
+        braced!(in_braces in input);
+
+        while !in_braces.is_empty() {
+
+            let ident: Ident = in_braces.parse()?;
+            in_braces.parse::<Token![:]>()?;
+
+            match ident.to_string().as_ref() {
+                "format" => {
+                    if format.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    let format_value: Ident = in_braces.parse()?;
+                    format = Some(format_value);
+                },
+                "name" => {
+                    if name.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    let name_value: LitStr = in_braces.parse()?;
+                    name = Some(name_value);
+                },
+                _ => return Err(unexpected_key(&ident, &["format", "name"])),
+            }
+
+            if !in_braces.is_empty() {
+                in_braces.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(Self {
+            format: format.ok_or_else(|| missing_key(span, "format"))?,
+            name: name.ok_or_else(|| missing_key(span, "name"))?,
+         })
+    }
+}
+
+/// One entry of `ShaderInterfaceDef`. A matrix or an array will use
+/// several locations.
+pub struct InterfaceEntry {
+    location_start: u32,
+    location_end: u32,
+    format: Ident,
+    name: String,
+}
+
+/// Elements declared in the macro get consecutive locations, in order.
+pub fn entries_from_elements(elements: &[InterfaceElement]) -> Vec<InterfaceEntry> {
+    elements.iter().enumerate().map(|(index, element)| {
+        InterfaceEntry {
+            location_start: index as u32,
+            location_end: index as u32 + 1,
+            format: element.format.clone(),
+            name: element.name.value(),
+        }
+    }).collect()
+}
+
+/// Find the `in` or `out` variables of the entry point. Built-ins such as
+/// `gl_Position` do not have a location so they are skipped.
+///
+/// For geometry and tessellation stages, the per-vertex variables are arrays
+/// of the real type, so `arrayed` strips the outer array.
+///
+/// The members of an interface block such as `out VS_OUT { vec3 n; } o;` take
+/// consecutive locations starting at the location of the block, unless they
+/// have their own.
+pub fn reflect_entries(spirv: &Spirv, entry_name: &str, wanted_storage: u32, arrayed: bool) -> std::result::Result<Vec<InterfaceEntry>, String> {
+    let mut entries = vec![];
+    for id in spirv.entry_point_interface(entry_name) {
+        let (storage_class, type_id) = match spirv.variable(*id) {
+            Some(var) => var,
+            None => continue,
+        };
+
+        if storage_class != wanted_storage || spirv.decoration(*id, DECORATION_BUILTIN).is_some() {
+            continue;
+        }
+
+        let location = spirv.decoration(*id, DECORATION_LOCATION).map(|params| params[0]);
+        let mut ty = spirv.resolve_type(type_id);
+        if arrayed && spirv.decoration(*id, DECORATION_PATCH).is_none() {
+            if let Type::Array { element, .. } = ty {
+                ty = *element;
+            }
+        }
+
+        let name = spirv.name(*id).to_owned();
+        let (struct_id, members) = match ty {
+            Type::Struct { id: struct_id, members } => (struct_id, members),
+            ty => {
+                let location = match location {
+                    Some(location) => location,
+                    None => continue,
+                };
+
+                entries.push(interface_entry(&ty, location, name)?);
+                continue;
+            },
+        };
+
+        // gl_PerVertex and the other built-in blocks.
+        if (0..members.len() as u32).any(|index| spirv.member_decoration(struct_id, index, DECORATION_BUILTIN).is_some()) {
+            continue;
+        }
+
+        let mut next_location = location;
+        for (index, member) in members.iter().enumerate() {
+            let member_name = format!("{}.{}", name, spirv.member_name(struct_id, index as u32));
+            let member_location = spirv.member_decoration(struct_id, index as u32, DECORATION_LOCATION)
+                .map(|params| params[0])
+                .or(next_location)
+                .ok_or_else(|| format!("the interface variable {} does not have a location", member_name))?;
+
+            let entry = interface_entry(member, member_location, member_name)?;
+            next_location = Some(entry.location_end);
+            entries.push(entry);
+        }
+    }
+
+    entries.sort_by_key(|e| e.location_start);
+    Ok(entries)
+}
+
+fn interface_entry(ty: &Type, location: u32, name: String) -> std::result::Result<InterfaceEntry, String> {
+    let (format, num_locations) = format_of(ty).ok_or_else(|| {
+        format!("cannot find the format of the interface variable {}, describe the interface with `input` and `output` instead", name)
+    })?;
+
+    Ok(InterfaceEntry {
+        location_start: location,
+        location_end: location + num_locations,
+        format: Ident::new(format, Span::call_site()),
+        name,
+    })
+}
+
+/// Vulkan format of an interface variable and the number of locations it
+/// takes. 64 bits vectors with 3 or 4 components use two locations.
+fn format_of(ty: &Type) -> Option<(&'static str, u32)> {
+    match ty {
+        Type::Float { width: 32 } => Some(("R32Sfloat", 1)),
+        Type::Float { width: 64 } => Some(("R64Sfloat", 1)),
+        Type::Int { width: 32, signed: true } => Some(("R32Sint", 1)),
+        Type::Int { width: 32, signed: false } => Some(("R32Uint", 1)),
+        Type::Vector { component, count } => {
+            let format = match (&**component, count) {
+                (Type::Float { width: 32 }, 2) => "R32G32Sfloat",
+                (Type::Float { width: 32 }, 3) => "R32G32B32Sfloat",
+                (Type::Float { width: 32 }, 4) => "R32G32B32A32Sfloat",
+                (Type::Float { width: 64 }, 2) => "R64G64Sfloat",
+                (Type::Float { width: 64 }, 3) => "R64G64B64Sfloat",
+                (Type::Float { width: 64 }, 4) => "R64G64B64A64Sfloat",
+                (Type::Int { width: 32, signed: true }, 2) => "R32G32Sint",
+                (Type::Int { width: 32, signed: true }, 3) => "R32G32B32Sint",
+                (Type::Int { width: 32, signed: true }, 4) => "R32G32B32A32Sint",
+                (Type::Int { width: 32, signed: false }, 2) => "R32G32Uint",
+                (Type::Int { width: 32, signed: false }, 3) => "R32G32B32Uint",
+                (Type::Int { width: 32, signed: false }, 4) => "R32G32B32A32Uint",
+                _ => return None,
+            };
+
+            let num_locations = match **component {
+                Type::Float { width: 64 } if *count > 2 => 2,
+                _ => 1,
+            };
+            Some((format, num_locations))
+        },
+        // A matrix is passed column by column.
+        Type::Matrix { column, count } => {
+            format_of(column).map(|(format, locations)| (format, locations * count))
+        },
+        Type::Array { element, length, .. } => {
+            format_of(element).map(|(format, locations)| (format, locations * length))
+        },
+        _ => None,
+    }
+}
+
+pub fn generate_interface(struct_name: Ident, entries: &[InterfaceEntry]) -> proc_macro2::TokenStream {
+
+    let mut input_impl = vec!();
+    for (index, entry) in entries.iter().enumerate() {
+
+        let index = index as u16;
+        let name = &entry.name;
+        let format = &entry.format;
+        let location_start = entry.location_start;
+        let location_end = entry.location_end;
+        input_impl.push(quote!(
+                if self.0 == #index {
+                    self.0 += 1;
+                    return Some(ShaderInterfaceDefEntry {
+                        location: #location_start..#location_end,
+                        format: Format::#format,
+                        name: Some(Cow::Borrowed(#name))
+                    })
+                }
+        ));
+    }
+
+    let mut iter_name = struct_name.to_string();
+    iter_name.push_str("Iter");
+    let iter_name = Ident::new(iter_name.as_str(), Span::call_site());
+
+    let length = entries.len();
+    quote!(
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub struct #struct_name;
+
+        unsafe impl ShaderInterfaceDef for #struct_name {
+            type Iter = #iter_name;
+
+            fn elements(&self) -> #iter_name {
+                #iter_name(0)
+            }
+        }
+
+        #[derive(Debug, Copy, Clone)]
+        pub struct #iter_name(u16);
+        impl Iterator for #iter_name {
+            type Item = ShaderInterfaceDefEntry;
+
+            #[inline]
+            fn next(&mut self) -> Option<Self::Item> {
+                #( #input_impl )*
+                None
+            }
+
+            #[inline]
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let len = #length - self.0 as usize;
+                (len, Some(len))
+            }
+        }
+
+        impl ExactSizeIterator for #iter_name { }
+        )
+}
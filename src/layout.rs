@@ -0,0 +1,1104 @@
+//! Rust representation of the structs shared with the shader (uniform blocks,
+//! push constants...). Every field is placed at the offset the shader expects
+//! by adding explicit padding between fields.
+
+use syn::Ident;
+use proc_macro2::Span;
+use std::collections::BTreeMap;
+use crate::spirv::{Spirv, Type, DECORATION_MATRIX_STRIDE, DECORATION_OFFSET, DECORATION_ROW_MAJOR};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scalar {
+    Float,
+    Double,
+    Int,
+    Uint,
+}
+
+impl Scalar {
+
+    pub fn size(&self) -> usize {
+        match *self {
+            Scalar::Double => 8,
+            _ => 4,
+        }
+    }
+
+    pub fn rust_type(&self) -> proc_macro2::TokenStream {
+        match *self {
+            Scalar::Float => quote!(f32),
+            Scalar::Double => quote!(f64),
+            Scalar::Int => quote!(i32),
+            Scalar::Uint => quote!(u32),
+        }
+    }
+}
+
+/// Type of a field once the strides are known.
+#[derive(Debug, Clone)]
+pub enum FieldType {
+    Scalar(Scalar),
+    Vector(Scalar, usize),
+    // Matrices are arrays of column vectors.
+    Array { element: Box<FieldType>, length: usize, stride: usize },
+    // Another generated struct.
+    Struct { name: Ident, size: usize },
+}
+
+impl FieldType {
+
+    pub fn size(&self) -> usize {
+        match self {
+            FieldType::Scalar(scalar) => scalar.size(),
+            FieldType::Vector(scalar, count) => scalar.size() * count,
+            FieldType::Array { length, stride, .. } => length * stride,
+            FieldType::Struct { size, .. } => *size,
+        }
+    }
+
+    pub fn rust_type(&self) -> proc_macro2::TokenStream {
+        match self {
+            FieldType::Scalar(scalar) => scalar.rust_type(),
+            FieldType::Vector(scalar, count) => {
+                let scalar = scalar.rust_type();
+                quote!([#scalar; #count])
+            },
+            FieldType::Array { element, length, stride } => {
+                let element = element_rust_type(element, *stride);
+                quote!([#element; #length])
+            },
+            FieldType::Struct { name, .. } => quote!(#name),
+        }
+    }
+}
+
+/// Rust type of an array element. Elements smaller than the stride are padded
+/// with extra components, for example a vec3 column of a mat3 becomes [f32; 4].
+fn element_rust_type(element: &FieldType, stride: usize) -> proc_macro2::TokenStream {
+    if element.size() == stride {
+        return element.rust_type();
+    }
+
+    match *element {
+        FieldType::Scalar(scalar) | FieldType::Vector(scalar, _) if stride % scalar.size() == 0 => {
+            let count = stride / scalar.size();
+            let scalar = scalar.rust_type();
+            quote!([#scalar; #count])
+        },
+        _ => panic!(format!("Array stride {} is not supported for {:?}", stride, element)),
+    }
+}
+
+/// A runtime sized array cannot be part of a Sized struct. The struct stops where
+/// the array starts and the type of the elements is exported as `<Name>Element`.
+///
+/// Rust rounds the size of the struct up to its alignment, so the array can
+/// start before the end of the struct, e.g. at offset 20 after a dvec2 and a
+/// float. The elements are written at `<Name>::ARRAY_OFFSET`.
+pub fn generate_runtime_array(name: &Ident, fields: &[Field], offset: usize, element: &FieldType, stride: usize) -> proc_macro2::TokenStream {
+    let structure = generate_struct_definition(name, fields, offset);
+    let alias = Ident::new(&format!("{}Element", name), Span::call_site());
+    let element = element_rust_type(element, stride);
+    let size_check = size_check_name(name);
+    quote!(
+        #structure
+
+        impl #name {
+            /// Offset of the runtime sized array in the buffer.
+            pub const ARRAY_OFFSET: usize = #offset;
+        }
+
+        pub type #alias = #element;
+
+        #[allow(dead_code)]
+        const #size_check: [(); (#offset + ::std::mem::align_of::<#name>() - 1) / ::std::mem::align_of::<#name>() * ::std::mem::align_of::<#name>()]
+            = [(); ::std::mem::size_of::<#name>()];
+    )
+}
+
+/// Layout rules of the buffer blocks. Uniform buffers use std140, storage
+/// buffers and push constants use std430.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Std {
+    Std140,
+    Std430,
+}
+
+impl Std {
+
+    /// Suffix of the structs generated again for this layout.
+    fn suffix(&self) -> &'static str {
+        match *self {
+            Std::Std140 => "Std140",
+            Std::Std430 => "Std430",
+        }
+    }
+}
+
+/// Struct declared in the macro input. Its name can be used as a field type.
+pub struct StructDecl {
+    pub name: Ident,
+    pub fields: Vec<(Ident, String)>,
+}
+
+/// Type of a field as written in the macro input, for example "vec3".
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlslType {
+    Scalar(Scalar),
+    Vector(Scalar, usize),
+    Matrix { scalar: Scalar, columns: usize, rows: usize },
+    Array(Box<GlslType>, usize),
+    Struct { name: Ident, members: Vec<(Ident, GlslType)> },
+}
+
+impl GlslType {
+
+    /// Parse the scalars (`float`, `double`, `int`, `uint`, `bool`), the vectors
+    /// (`vecN`, `dvecN`, `ivecN`, `uvecN`, `bvecN`) and the matrices (`matN`,
+    /// `matCxR` and their `dmat` versions).
+    ///
+    /// Arrays are written like in GLSL, `vec4[8]` or `Light[4][2]`, and the
+    /// structs are the ones declared in `structs`.
+    pub fn parse(repr: &str, structs: &[StructDecl]) -> Option<Self> {
+        GlslType::parse_nested(repr, structs, 0)
+    }
+
+    // A struct cannot contain itself, so a valid declaration is never nested
+    // deeper than the number of structs.
+    fn parse_nested(repr: &str, structs: &[StructDecl], depth: usize) -> Option<Self> {
+        // The first size is the outermost array.
+        if let Some(index) = repr.find('[') {
+            if !repr.ends_with(']') {
+                return None;
+            }
+
+            let mut ty = GlslType::parse_nested(&repr[..index], structs, depth)?;
+            let lengths = repr[index + 1..repr.len() - 1].split("][")
+                .map(|length| length.parse::<usize>().ok().filter(|length| *length > 0))
+                .collect::<Option<Vec<_>>>()?;
+            for length in lengths.into_iter().rev() {
+                ty = GlslType::Array(Box::new(ty), length);
+            }
+
+            return Some(ty);
+        }
+
+        if let Some(ty) = GlslType::parse_builtin(repr) {
+            return Some(ty);
+        }
+
+        let decl = structs.iter().find(|decl| decl.name == repr)?;
+        if depth >= structs.len() {
+            return None;
+        }
+
+        let members = decl.fields.iter()
+            .map(|(field, ty)| GlslType::parse_nested(ty, structs, depth + 1).map(|ty| (field.clone(), ty)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(GlslType::Struct { name: decl.name.clone(), members })
+    }
+
+    /// Scalars, vectors and matrices. The prefixes are matched exactly so a
+    /// struct named e.g. `Format` or `Animation` is not taken for one of them.
+    fn parse_builtin(repr: &str) -> Option<Self> {
+        fn dimension(repr: &str) -> Option<usize> {
+            match repr {
+                "2" => Some(2),
+                "3" => Some(3),
+                "4" => Some(4),
+                _ => None,
+            }
+        }
+
+        const VECTORS: &[(&str, Scalar)] = &[
+            ("vec", Scalar::Float),
+            ("dvec", Scalar::Double),
+            ("ivec", Scalar::Int),
+            ("uvec", Scalar::Uint),
+            // Booleans are stored as 32 bits integers.
+            ("bvec", Scalar::Uint),
+        ];
+        const MATRICES: &[(&str, Scalar)] = &[("mat", Scalar::Float), ("dmat", Scalar::Double)];
+
+        let ty = match repr {
+            "float" => GlslType::Scalar(Scalar::Float),
+            "double" => GlslType::Scalar(Scalar::Double),
+            "int" => GlslType::Scalar(Scalar::Int),
+            "uint" | "bool" => GlslType::Scalar(Scalar::Uint),
+            _ => {
+                if let Some((prefix, scalar)) = VECTORS.iter().find(|(prefix, _)| repr.starts_with(prefix)) {
+                    return dimension(&repr[prefix.len()..]).map(|count| GlslType::Vector(*scalar, count));
+                }
+
+                let (prefix, scalar) = MATRICES.iter().find(|(prefix, _)| repr.starts_with(prefix))?;
+
+                // matCxR has C columns of R rows.
+                let size = &repr[prefix.len()..];
+                let (columns, rows) = match size.find('x') {
+                    Some(x) => (dimension(&size[..x])?, dimension(&size[x + 1..])?),
+                    None => (dimension(size)?, dimension(size)?),
+                };
+                GlslType::Matrix { scalar: *scalar, columns, rows }
+            },
+        };
+
+        Some(ty)
+    }
+
+    /// Base alignment of the type. vec3 is aligned like a vec4.
+    pub fn alignment(&self, std: Std) -> usize {
+        match self {
+            GlslType::Scalar(scalar) => scalar.size(),
+            GlslType::Vector(scalar, 2) => scalar.size() * 2,
+            GlslType::Vector(scalar, _) => scalar.size() * 4,
+            GlslType::Matrix { scalar, rows, .. } => array_alignment(&GlslType::Vector(*scalar, *rows), std),
+            GlslType::Array(element, _) => array_alignment(element, std),
+            // In std140, a struct is aligned to 16 bytes like the arrays.
+            GlslType::Struct { members, .. } => {
+                let alignment = members.iter().map(|(_, ty)| ty.alignment(std)).max().unwrap_or(1);
+                match std {
+                    Std::Std140 => round_up(alignment, 16),
+                    Std::Std430 => alignment,
+                }
+            },
+        }
+    }
+
+    pub fn field_type(&self, std: Std) -> FieldType {
+        match self {
+            GlslType::Scalar(scalar) => FieldType::Scalar(*scalar),
+            GlslType::Vector(scalar, count) => FieldType::Vector(*scalar, *count),
+            // A matrix is an array of column vectors.
+            GlslType::Matrix { scalar, columns, rows } => {
+                let column = GlslType::Vector(*scalar, *rows);
+                FieldType::Array {
+                    element: Box::new(column.field_type(std)),
+                    length: *columns,
+                    stride: array_stride(&column, std),
+                }
+            },
+            GlslType::Array(element, length) => FieldType::Array {
+                element: Box::new(element.field_type(std)),
+                length: *length,
+                stride: array_stride(element, std),
+            },
+            GlslType::Struct { name, members } => FieldType::Struct {
+                name: name.clone(),
+                size: std_layout(members, std).1,
+            },
+        }
+    }
+}
+
+fn round_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// In std140, the elements of an array are aligned to 16 bytes.
+fn array_alignment(element: &GlslType, std: Std) -> usize {
+    match std {
+        Std::Std140 => round_up(element.alignment(std), 16),
+        Std::Std430 => element.alignment(std),
+    }
+}
+
+fn array_stride(element: &GlslType, std: Std) -> usize {
+    round_up(element.field_type(std).size(), array_alignment(element, std))
+}
+
+/// Offset and stride of a runtime sized array placed after the fields.
+pub fn runtime_array_layout(fields: &[Field], element: &GlslType, std: Std) -> (usize, usize) {
+    let end = fields.last().map(|f| f.offset + f.ty.size()).unwrap_or(0);
+    (round_up(end, array_alignment(element, std)), array_stride(element, std))
+}
+
+/// Place the fields one after the other following the std140 or std430 rules.
+/// Returns the fields and the size of the struct.
+pub fn std_layout(members: &[(Ident, GlslType)], std: Std) -> (Vec<Field>, usize) {
+
+    let mut fields = vec![];
+    let mut offset = 0;
+    let mut struct_alignment = 1;
+    for (name, ty) in members {
+        let alignment = ty.alignment(std);
+        struct_alignment = struct_alignment.max(alignment);
+        offset = round_up(offset, alignment);
+
+        let ty = ty.field_type(std);
+        let size = ty.size();
+        fields.push(Field { name: name.clone(), ty, offset });
+        offset += size;
+    }
+
+    if std == Std::Std140 {
+        struct_alignment = round_up(struct_alignment, 16);
+    }
+
+    (fields, round_up(offset, struct_alignment))
+}
+
+/// Generate the declared structs used by a field, the nested ones first, and
+/// return the type with the names of the generated structs. A struct used by a
+/// std140 and a std430 block has different strides in each, so the second one
+/// gets the layout as suffix, e.g. `LightStd430`.
+pub fn generate_declared_structs(ty: &GlslType, std: Std, structs: &mut BTreeMap<String, proc_macro2::TokenStream>) -> GlslType {
+    match ty {
+        GlslType::Array(element, length) => GlslType::Array(Box::new(generate_declared_structs(element, std, structs)), *length),
+        GlslType::Struct { name, members } => {
+            let members: Vec<_> = members.iter()
+                .map(|(field, member)| (field.clone(), generate_declared_structs(member, std, structs)))
+                .collect();
+
+            let (fields, size) = std_layout(&members, std);
+            let name = insert_struct(structs, name, std.suffix(), |name| generate_struct(name, &fields, size));
+            GlslType::Struct { name, members }
+        },
+        ty => ty.clone(),
+    }
+}
+
+/// The fields laid out from the macro input have to be placed and typed like
+/// the members of the block, including the members of the nested structs. A
+/// runtime sized array is the last member of the block.
+pub fn check_block(spirv: &Spirv,
+                   block: &Type,
+                   name: &Ident,
+                   members: &[(Ident, GlslType)],
+                   runtime_array: Option<(&Ident, &GlslType)>,
+                   std: Std) -> syn::Result<()> {
+
+    let (id, spirv_members) = match block {
+        Type::Struct { id, members } => (*id, members),
+        _ => return Err(syn::Error::new_spanned(name, format!("the block of {} is not a struct", name))),
+    };
+
+    let count = members.len() + runtime_array.iter().count();
+    if count != spirv_members.len() {
+        return Err(syn::Error::new_spanned(name, format!("{} has {} fields but the block in the shader has {} members",
+                                                         name, count, spirv_members.len())));
+    }
+
+    let (fields, _) = std_layout(members, std);
+    for (index, (field, (field_name, ty))) in fields.iter().zip(members.iter()).enumerate() {
+        let what = format!("field {} of {}", field_name, name);
+        check_member(spirv, id, index as u32, &spirv_members[index], ty, field.offset, std, field_name, &what)?;
+    }
+
+    if let Some((field_name, element)) = runtime_array {
+        let index = members.len();
+        let what = format!("field {} of {}", field_name, name);
+        let (offset, stride) = runtime_array_layout(&fields, element, std);
+        check_offset(spirv, id, index as u32, offset, field_name, &what)?;
+        match &spirv_members[index] {
+            Type::RuntimeArray { element: spirv_element, stride: spirv_stride } => {
+                check_stride(stride, *spirv_stride, field_name, &what)?;
+                let matrix_stride = member_matrix_stride(spirv, id, index as u32, field_name, &what)?;
+                check_type(spirv, element, spirv_element, matrix_stride, std, field_name, &format!("an element of {}", what))?;
+            },
+            ty => return Err(type_mismatch(spirv, &format!("{}[]", glsl_name(element)), ty, field_name, &what)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the offset and the type of the member `index` of the struct `id`.
+fn check_member(spirv: &Spirv,
+                id: u32,
+                index: u32,
+                spirv_ty: &Type,
+                ty: &GlslType,
+                offset: usize,
+                std: Std,
+                span: &Ident,
+                what: &str) -> syn::Result<()> {
+
+    check_offset(spirv, id, index, offset, span, what)?;
+    let matrix_stride = member_matrix_stride(spirv, id, index, span, what)?;
+    check_type(spirv, ty, spirv_ty, matrix_stride, std, span, what)
+}
+
+/// The layout only has column major matrices.
+fn member_matrix_stride(spirv: &Spirv, id: u32, index: u32, span: &Ident, what: &str) -> syn::Result<Option<usize>> {
+    if spirv.member_decoration(id, index, DECORATION_ROW_MAJOR).is_some() {
+        return Err(syn::Error::new_spanned(span, format!("{} is row major in the shader, which is not supported", what)));
+    }
+
+    Ok(spirv.member_decoration(id, index, DECORATION_MATRIX_STRIDE).map(|params| params[0] as usize))
+}
+
+fn check_offset(spirv: &Spirv, id: u32, index: u32, offset: usize, span: &Ident, what: &str) -> syn::Result<()> {
+    match spirv.member_decoration(id, index, DECORATION_OFFSET).map(|params| params[0] as usize) {
+        Some(spirv_offset) if spirv_offset == offset => Ok(()),
+        Some(spirv_offset) => Err(syn::Error::new_spanned(span, format!("{} is at offset {} but the shader expects it at offset {}",
+                                                                        what, offset, spirv_offset))),
+        None => Err(syn::Error::new_spanned(span, format!("{} does not have an offset in the shader", what))),
+    }
+}
+
+fn check_stride(stride: usize, spirv_stride: Option<u32>, span: &Ident, what: &str) -> syn::Result<()> {
+    match spirv_stride {
+        Some(spirv_stride) if spirv_stride as usize == stride => Ok(()),
+        Some(spirv_stride) => Err(syn::Error::new_spanned(span, format!("the elements of {} are {} bytes apart but {} in the shader",
+                                                                        what, stride, spirv_stride))),
+        None => Err(syn::Error::new_spanned(span, format!("{} does not have an array stride in the shader", what))),
+    }
+}
+
+/// Compare a type of the macro input with the type of the SPIR-V. The
+/// members of a struct are checked like the members of the block.
+fn check_type(spirv: &Spirv,
+              ty: &GlslType,
+              spirv_ty: &Type,
+              matrix_stride: Option<usize>,
+              std: Std,
+              span: &Ident,
+              what: &str) -> syn::Result<()> {
+
+    let matching = match (ty, spirv_ty) {
+        (GlslType::Scalar(scalar), spirv_ty) => spirv_scalar(spirv_ty) == Some(*scalar),
+        (GlslType::Vector(scalar, count), Type::Vector { component, count: spirv_count }) => {
+            spirv_scalar(component) == Some(*scalar) && *count == *spirv_count as usize
+        },
+        (GlslType::Matrix { scalar, columns, rows }, Type::Matrix { column, count }) => {
+            let column_matching = match &**column {
+                Type::Vector { component, count: spirv_rows } => spirv_scalar(component) == Some(*scalar) && *rows == *spirv_rows as usize,
+                _ => false,
+            };
+
+            if column_matching && *columns == *count as usize {
+                let stride = array_stride(&GlslType::Vector(*scalar, *rows), std);
+                return match matrix_stride {
+                    Some(matrix_stride) if matrix_stride == stride => Ok(()),
+                    Some(matrix_stride) => Err(syn::Error::new_spanned(span, format!(
+                        "the columns of {} are {} bytes apart but {} in the shader", what, stride, matrix_stride))),
+                    None => Err(syn::Error::new_spanned(span, format!("{} does not have a matrix stride in the shader", what))),
+                };
+            }
+
+            false
+        },
+        (GlslType::Array(element, length), Type::Array { element: spirv_element, length: spirv_length, stride }) => {
+            if *length == *spirv_length as usize {
+                check_stride(array_stride(element, std), *stride, span, what)?;
+                return check_type(spirv, element, spirv_element, matrix_stride, std, span, &format!("an element of {}", what));
+            }
+
+            false
+        },
+        (GlslType::Struct { members, .. }, Type::Struct { id, members: spirv_members }) => {
+            if members.len() != spirv_members.len() {
+                return Err(syn::Error::new_spanned(span, format!("{} has {} members but {} in the shader",
+                                                                 what, members.len(), spirv_members.len())));
+            }
+
+            let (fields, _) = std_layout(members, std);
+            for (index, (field, (member_name, member_ty))) in fields.iter().zip(members.iter()).enumerate() {
+                let what = format!("member {} of {}", member_name, what);
+                check_member(spirv, *id, index as u32, &spirv_members[index], member_ty, field.offset, std, span, &what)?;
+            }
+
+            return Ok(());
+        },
+        _ => false,
+    };
+
+    if matching {
+        Ok(())
+    } else {
+        Err(type_mismatch(spirv, &glsl_name(ty), spirv_ty, span, what))
+    }
+}
+
+fn type_mismatch(spirv: &Spirv, expected: &str, spirv_ty: &Type, span: &Ident, what: &str) -> syn::Error {
+    syn::Error::new_spanned(span, format!("{} is declared as {} but is {} in the shader", what, expected, spirv_name(spirv, spirv_ty)))
+}
+
+/// Scalar of the layout stored in a SPIR-V scalar type.
+fn spirv_scalar(ty: &Type) -> Option<Scalar> {
+    match ty {
+        // Booleans are stored as 32 bits integers.
+        Type::Bool => Some(Scalar::Uint),
+        Type::Int { width: 32, signed: true } => Some(Scalar::Int),
+        Type::Int { width: 32, signed: false } => Some(Scalar::Uint),
+        Type::Float { width: 32 } => Some(Scalar::Float),
+        Type::Float { width: 64 } => Some(Scalar::Double),
+        _ => None,
+    }
+}
+
+/// GLSL spelling of a type, for the errors.
+fn glsl_name(ty: &GlslType) -> String {
+    fn prefix(scalar: Scalar) -> &'static str {
+        match scalar {
+            Scalar::Float => "",
+            Scalar::Double => "d",
+            Scalar::Int => "i",
+            Scalar::Uint => "u",
+        }
+    }
+
+    match ty {
+        GlslType::Scalar(Scalar::Float) => "float".to_owned(),
+        GlslType::Scalar(Scalar::Double) => "double".to_owned(),
+        GlslType::Scalar(Scalar::Int) => "int".to_owned(),
+        GlslType::Scalar(Scalar::Uint) => "uint".to_owned(),
+        GlslType::Vector(scalar, count) => format!("{}vec{}", prefix(*scalar), count),
+        GlslType::Matrix { scalar: Scalar::Double, columns, rows } => format!("dmat{}x{}", columns, rows),
+        GlslType::Matrix { columns, rows, .. } => format!("mat{}x{}", columns, rows),
+        GlslType::Array(element, length) => format!("{}[{}]", glsl_name(element), length),
+        GlslType::Struct { name, .. } => name.to_string(),
+    }
+}
+
+/// GLSL spelling of a type of the SPIR-V, for the errors.
+pub fn spirv_name(spirv: &Spirv, ty: &Type) -> String {
+    let scalar_name = |ty: &Type| spirv_scalar(ty).map(|scalar| glsl_name(&GlslType::Scalar(scalar)));
+    let vector_name = |component: &Type, count: u32| {
+        spirv_scalar(component).map(|scalar| glsl_name(&GlslType::Vector(scalar, count as usize)))
+    };
+
+    let name = match ty {
+        Type::Vector { component, count } => {
+            vector_name(component, *count).or_else(|| Some(format!("vector of {} {}", count, spirv_name(spirv, component))))
+        },
+        Type::Matrix { column, count } => match &**column {
+            Type::Vector { component, count: rows } => spirv_scalar(component).map(|scalar| {
+                glsl_name(&GlslType::Matrix { scalar, columns: *count as usize, rows: *rows as usize })
+            }),
+            _ => None,
+        },
+        Type::Array { element, length, .. } => Some(format!("{}[{}]", spirv_name(spirv, element), length)),
+        Type::RuntimeArray { element, .. } => Some(format!("{}[]", spirv_name(spirv, element))),
+        Type::Struct { id, .. } => Some(format!("struct {}", spirv.name(*id))),
+        Type::Int { width, signed } if scalar_name(ty).is_none() => Some(format!("{}int{}_t", if *signed { "" } else { "u" }, width)),
+        Type::Float { width } if scalar_name(ty).is_none() => Some(format!("float{}_t", width)),
+        ty => scalar_name(ty),
+    };
+
+    name.unwrap_or_else(|| format!("{:?}", ty))
+}
+
+pub struct Field {
+    pub name: Ident,
+    pub ty: FieldType,
+    pub offset: usize,
+}
+
+/// Generate a `#[repr(C)]` struct of the given size, with padding fields
+/// between the fields that are not contiguous.
+pub fn generate_struct(name: &Ident, fields: &[Field], size: usize) -> proc_macro2::TokenStream {
+    let structure = generate_struct_definition(name, fields, size);
+
+    // Fails to compile if rustc did not come up with the same size.
+    let size_check = size_check_name(name);
+    quote!(
+        #structure
+
+        #[allow(dead_code)]
+        const #size_check: [(); #size] = [(); ::std::mem::size_of::<#name>()];
+    )
+}
+
+fn size_check_name(name: &Ident) -> Ident {
+    Ident::new(&format!("_{}_SIZE_CHECK", name.to_string().to_uppercase()), Span::call_site())
+}
+
+/// The struct padded up to `size`, without the check of its size.
+fn generate_struct_definition(name: &Ident, fields: &[Field], size: usize) -> proc_macro2::TokenStream {
+
+    fn add_padding(content: &mut Vec<proc_macro2::TokenStream>, current_offset: usize, offset: usize) {
+        if offset > current_offset {
+            let pad_name = Ident::new(&format!("_pad{}", content.len()), Span::call_site());
+            let pad_size = offset - current_offset;
+            content.push(quote!(
+                    pub #pad_name: [u8; #pad_size],
+                    ));
+        }
+    }
+
+    let mut content = vec![];
+    let mut current_offset = 0;
+    for field in fields {
+        if field.offset < current_offset {
+            panic!(format!("Field {} of {} overlaps the previous field", field.name, name));
+        }
+
+        add_padding(&mut content, current_offset, field.offset);
+        let field_name = &field.name;
+        let field_ty = field.ty.rust_type();
+        content.push(quote!(
+                pub #field_name: #field_ty,
+                ));
+        current_offset = field.offset + field.ty.size();
+    }
+
+    add_padding(&mut content, current_offset, size);
+
+    quote!(
+        #[repr(C)]
+        #[derive(Debug, Clone, Copy)]
+        pub struct #name {
+            #( #content )*
+        }
+    )
+}
+
+/// Generate the Rust struct of a block found in the SPIR-V, and the structs
+/// of its nested members. Returns the size of the block.
+///
+/// If the last member is a runtime sized array, the size is the offset of the array.
+///
+/// The block keeps `name`, so a nested struct with the same name gets a
+/// suffix. `name` cannot be a struct generated for another block.
+pub fn reflect_struct(spirv: &Spirv,
+                      ty: &Type,
+                      name: &Ident,
+                      std: Std,
+                      structs: &mut BTreeMap<String, proc_macro2::TokenStream>) -> syn::Result<usize> {
+
+    if structs.contains_key(&name.to_string()) {
+        return Err(syn::Error::new_spanned(name, format!("{} is also the name of a struct of the shader, choose another name", name)));
+    }
+
+    // Nothing generates an empty struct, so `insert_struct` renames the nested
+    // structs named like the block.
+    structs.insert(name.to_string(), quote!());
+
+    let (fields, runtime_array) = reflect_members(spirv, ty, name, &name.to_string(), std, structs)?;
+    if let Some((offset, element, stride)) = runtime_array {
+        structs.insert(name.to_string(), generate_runtime_array(name, &fields, offset, &element, stride));
+        return Ok(offset);
+    }
+
+    let size = fields.last().map(|f| f.offset + f.ty.size()).unwrap_or(0);
+    structs.insert(name.to_string(), generate_struct(name, &fields, size));
+    Ok(size)
+}
+
+/// Fields of a struct of the SPIR-V, and its runtime sized array if it has one.
+/// The errors point at `span`, the name of the block in the macro input, and
+/// `what` is the struct in their message.
+fn reflect_members(spirv: &Spirv,
+                   ty: &Type,
+                   span: &Ident,
+                   what: &str,
+                   std: Std,
+                   structs: &mut BTreeMap<String, proc_macro2::TokenStream>) -> syn::Result<(Vec<Field>, Option<(usize, FieldType, usize)>)> {
+
+    let (id, members) = match ty {
+        Type::Struct { id, members } => (*id, members),
+        _ => return Err(syn::Error::new_spanned(span, format!("the block of {} is not a struct", what))),
+    };
+
+    let mut fields = vec![];
+    let mut runtime_array = None;
+    for (index, member) in members.iter().enumerate() {
+        let index = index as u32;
+        let name = rust_ident(spirv.member_name(id, index), || format!("member{}", index));
+        let what = format!("member {} of {}", name, what);
+        let offset = match spirv.member_decoration(id, index, DECORATION_OFFSET) {
+            Some(params) => params[0] as usize,
+            None => return Err(syn::Error::new_spanned(span, format!("{} does not have an offset in the shader", what))),
+        };
+        let matrix_stride = member_matrix_stride(spirv, id, index, span, &what)?;
+
+        if let Type::RuntimeArray { element, stride } = member {
+            let stride = reflected_stride(*stride, span, &what)?;
+            let element = reflect_field(spirv, element, matrix_stride, Some(stride), std, span, &format!("an element of {}", what), structs)?;
+            runtime_array = Some((offset, element, stride));
+            continue;
+        }
+
+        fields.push(Field {
+            ty: reflect_field(spirv, member, matrix_stride, None, std, span, &what, structs)?,
+            name,
+            offset,
+        });
+    }
+
+    Ok((fields, runtime_array))
+}
+
+fn reflected_stride(stride: Option<u32>, span: &Ident, what: &str) -> syn::Result<usize> {
+    stride.map(|stride| stride as usize)
+        .ok_or_else(|| syn::Error::new_spanned(span, format!("{} does not have an array stride in the shader", what)))
+}
+
+/// Rust name of something named in the SPIR-V. Modules without debug info
+/// have no names, and other compilers use names such as `type.Globals`.
+pub fn rust_ident<F: FnOnce() -> String>(name: &str, fallback: F) -> Ident {
+    let mut ident: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+    if ident.is_empty() {
+        ident = fallback();
+    }
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    Ident::new(&ident, Span::call_site())
+}
+
+/// Add a generated struct to `structs` and return its name. The same GLSL
+/// struct can be generated with different layouts, as a field and as the
+/// padded element of an array, or in a std140 and a std430 block. The first
+/// one keeps the GLSL name and the others get `suffix`, then a number.
+fn insert_struct<F>(structs: &mut BTreeMap<String, proc_macro2::TokenStream>, name: &Ident, suffix: &str, generate: F) -> Ident
+    where F: Fn(&Ident) -> proc_macro2::TokenStream {
+
+    let mut index = 0;
+    loop {
+        let candidate = match index {
+            0 => name.clone(),
+            1 => Ident::new(&format!("{}{}", name, suffix), name.span()),
+            _ => Ident::new(&format!("{}{}{}", name, suffix, index), name.span()),
+        };
+
+        let generated = generate(&candidate);
+        match structs.get(&candidate.to_string()) {
+            Some(previous) if previous.to_string() != generated.to_string() => index += 1,
+            Some(_) => return candidate,
+            None => {
+                structs.insert(candidate.to_string(), generated);
+                return candidate;
+            },
+        }
+    }
+}
+
+/// `padded_size` is used when the field is the element of an array with a
+/// bigger stride.
+fn reflect_field(spirv: &Spirv,
+                 ty: &Type,
+                 matrix_stride: Option<usize>,
+                 padded_size: Option<usize>,
+                 std: Std,
+                 span: &Ident,
+                 what: &str,
+                 structs: &mut BTreeMap<String, proc_macro2::TokenStream>) -> syn::Result<FieldType> {
+
+    if let Some(scalar) = spirv_scalar(ty) {
+        return Ok(FieldType::Scalar(scalar));
+    }
+
+    Ok(match ty {
+        Type::Vector { component, count } => match spirv_scalar(component) {
+            Some(scalar) => FieldType::Vector(scalar, *count as usize),
+            None => return Err(unsupported_type(spirv, ty, span, what)),
+        },
+        Type::Matrix { column, count } => {
+            let stride = matrix_stride.ok_or_else(|| {
+                syn::Error::new_spanned(span, format!("{} does not have a matrix stride in the shader", what))
+            })?;
+            FieldType::Array {
+                element: Box::new(reflect_field(spirv, column, None, None, std, span, what, structs)?),
+                length: *count as usize,
+                stride,
+            }
+        },
+        Type::Array { element, length, stride } => {
+            let stride = reflected_stride(*stride, span, what)?;
+            let what = format!("an element of {}", what);
+            FieldType::Array {
+                element: Box::new(reflect_field(spirv, element, matrix_stride, Some(stride), std, span, &what, structs)?),
+                length: *length as usize,
+                stride,
+            }
+        },
+        Type::Struct { id, .. } => {
+            let name = rust_ident(spirv.name(*id), || format!("Struct{}", id));
+            let (fields, _) = reflect_members(spirv, ty, span, what, std, structs)?;
+            let natural_size = fields.last().map(|f| f.offset + f.ty.size()).unwrap_or(0);
+            let size = padded_size.unwrap_or(natural_size).max(natural_size);
+            let suffix = if size > natural_size { "Padded" } else { std.suffix() };
+            let name = insert_struct(structs, &name, suffix, |name| generate_struct(name, &fields, size));
+            FieldType::Struct { name, size }
+        },
+        _ => return Err(unsupported_type(spirv, ty, span, what)),
+    })
+}
+
+fn unsupported_type(spirv: &Spirv, ty: &Type, span: &Ident, what: &str) -> syn::Error {
+    syn::Error::new_spanned(span, format!("{} is {} in the shader, which is not supported", what, spirv_name(spirv, ty)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spirv::Instruction;
+
+    fn ident(name: &str) -> Ident {
+        Ident::new(name, Span::call_site())
+    }
+
+    fn decl(name: &str, fields: &[(&str, &str)]) -> StructDecl {
+        StructDecl {
+            name: ident(name),
+            fields: fields.iter().map(|(field, ty)| (ident(field), ty.to_string())).collect(),
+        }
+    }
+
+    fn offsets(members: &[(&str, &str)], std: Std) -> (Vec<usize>, usize) {
+        let members: Vec<_> = members.iter().map(|(name, ty)| (ident(name), GlslType::parse(ty, &[]).unwrap())).collect();
+        let (fields, size) = std_layout(&members, std);
+        (fields.iter().map(|field| field.offset).collect(), size)
+    }
+
+    #[test]
+    fn std430_layout() {
+        // A vec3 is aligned like a vec4, and a float can follow it.
+        assert_eq!(offsets(&[("a", "float"), ("b", "vec3"), ("c", "float")], Std::Std430), (vec![0, 16, 28], 32));
+        assert_eq!(offsets(&[("a", "float"), ("b", "vec2")], Std::Std430), (vec![0, 8], 16));
+        assert_eq!(offsets(&[("a", "float"), ("b", "float[3]"), ("c", "float")], Std::Std430), (vec![0, 4, 16], 20));
+        assert_eq!(offsets(&[("a", "double"), ("b", "float")], Std::Std430), (vec![0, 8], 16));
+    }
+
+    #[test]
+    fn std140_layout() {
+        // Arrays are aligned to 16 bytes and the size is rounded up to 16.
+        assert_eq!(offsets(&[("a", "float"), ("b", "float[3]"), ("c", "float")], Std::Std140), (vec![0, 16, 64], 80));
+        assert_eq!(offsets(&[("a", "float"), ("b", "vec3"), ("c", "float")], Std::Std140), (vec![0, 16, 28], 32));
+        assert_eq!(offsets(&[("a", "vec2"), ("b", "mat3")], Std::Std140), (vec![0, 16], 64));
+    }
+
+    #[test]
+    fn matrix_columns() {
+        let mat3 = GlslType::parse("mat3", &[]).unwrap();
+        match mat3.field_type(Std::Std430) {
+            FieldType::Array { length: 3, stride: 16, .. } => (),
+            ty => panic!("mat3 has the field type {:?}", ty),
+        }
+
+        let mat2 = GlslType::parse("mat2", &[]).unwrap();
+        assert_eq!(mat2.field_type(Std::Std430).size(), 16);
+        assert_eq!(mat2.field_type(Std::Std140).size(), 32);
+    }
+
+    #[test]
+    fn array_strides() {
+        let parse = |repr| GlslType::parse(repr, &[]).unwrap();
+        assert_eq!(array_stride(&parse("float"), Std::Std430), 4);
+        assert_eq!(array_stride(&parse("float"), Std::Std140), 16);
+        assert_eq!(array_stride(&parse("vec3"), Std::Std430), 16);
+        assert_eq!(array_stride(&parse("dvec3"), Std::Std430), 32);
+        assert_eq!(array_stride(&parse("mat2"), Std::Std430), 16);
+        assert_eq!(array_stride(&parse("vec2[3]"), Std::Std430), 24);
+
+        let structs = [decl("Light", &[("position", "vec3"), ("radius", "float")])];
+        let light = GlslType::parse("Light", &structs).unwrap();
+        assert_eq!(array_stride(&light, Std::Std430), 16);
+        assert_eq!(array_stride(&light, Std::Std140), 16);
+    }
+
+    fn float() -> Type {
+        Type::Float { width: 32 }
+    }
+
+    fn vec3() -> Type {
+        Type::Vector { component: Box::new(float()), count: 3 }
+    }
+
+    // `Offset` decorations of the members of the struct `id`.
+    fn offset_decorations(id: u32, offsets: &[u32]) -> Vec<Instruction> {
+        offsets.iter().enumerate().map(|(index, offset)| {
+            Instruction::MemberDecorate { target_id: id, member: index as u32, decoration: DECORATION_OFFSET, params: vec![*offset] }
+        }).collect()
+    }
+
+    // `uniform Lights { Light lights[2]; }` where Light has the given members.
+    fn lights_block(light_members: Vec<Type>, light_offsets: &[u32]) -> (Spirv, Type) {
+        let light = Type::Struct { id: 10, members: light_members };
+        let lights = Type::Array { element: Box::new(light), length: 2, stride: Some(32) };
+        let mut instructions = offset_decorations(10, light_offsets);
+        instructions.extend(offset_decorations(20, &[0]));
+        (Spirv { instructions }, Type::Struct { id: 20, members: vec![lights] })
+    }
+
+    fn check_lights(spirv: &Spirv, block: &Type, light_fields: &[(&str, &str)]) -> syn::Result<()> {
+        let structs = [decl("Light", light_fields)];
+        let members = [(ident("lights"), GlslType::parse("Light[2]", &structs).unwrap())];
+        check_block(spirv, block, &ident("Lights"), &members, None, Std::Std140)
+    }
+
+    #[test]
+    fn check_nested_struct() {
+        let (spirv, block) = lights_block(vec![vec3(), vec3()], &[0, 16]);
+        assert!(check_lights(&spirv, &block, &[("color", "vec3"), ("position", "vec3")]).is_ok());
+    }
+
+    #[test]
+    fn check_nested_struct_members() {
+        // struct Light { vec3 position; float radius; vec3 color; } is also 32 bytes.
+        let (spirv, block) = lights_block(vec![vec3(), float(), vec3()], &[0, 12, 16]);
+        let error = check_lights(&spirv, &block, &[("color", "vec3"), ("position", "vec3")]).unwrap_err();
+        assert_eq!(error.to_string(), "an element of field lights of Lights has 2 members but 3 in the shader");
+
+        // struct Light { float radius; vec3 color; } has a float where a vec3 is declared.
+        let (spirv, block) = lights_block(vec![float(), vec3()], &[0, 16]);
+        let error = check_lights(&spirv, &block, &[("color", "vec3"), ("position", "vec3")]).unwrap_err();
+        assert_eq!(error.to_string(), "member color of an element of field lights of Lights is declared as vec3 but is float in the shader");
+    }
+
+    #[test]
+    fn check_scalar_kind() {
+        // uniform Data { int a; }
+        let spirv = Spirv { instructions: offset_decorations(20, &[0]) };
+        let block = Type::Struct { id: 20, members: vec![Type::Int { width: 32, signed: true }] };
+        let members = [(ident("a"), GlslType::parse("float", &[]).unwrap())];
+        let error = check_block(&spirv, &block, &ident("Data"), &members, None, Std::Std140).unwrap_err();
+        assert_eq!(error.to_string(), "field a of Data is declared as float but is int in the shader");
+
+        let members = [(ident("a"), GlslType::parse("int", &[]).unwrap())];
+        assert!(check_block(&spirv, &block, &ident("Data"), &members, None, Std::Std140).is_ok());
+    }
+
+    #[test]
+    fn check_array_length() {
+        // uniform Data { vec3 colors[4]; }
+        let spirv = Spirv { instructions: offset_decorations(20, &[0]) };
+        let colors = Type::Array { element: Box::new(vec3()), length: 4, stride: Some(16) };
+        let block = Type::Struct { id: 20, members: vec![colors] };
+        let members = [(ident("colors"), GlslType::parse("vec3[3]", &[]).unwrap())];
+        let error = check_block(&spirv, &block, &ident("Data"), &members, None, Std::Std140).unwrap_err();
+        assert_eq!(error.to_string(), "field colors of Data is declared as vec3[3] but is vec3[4] in the shader");
+    }
+
+    #[test]
+    fn reflect_nested_struct_named_like_the_block() {
+        // struct Light { vec3 color; vec3 position; }; uniform Lights { Light lights[2]; }
+        let (mut spirv, block) = lights_block(vec![vec3(), vec3()], &[0, 16]);
+        spirv.instructions.push(Instruction::Name { target_id: 10, name: "Light".to_owned() });
+        spirv.instructions.push(Instruction::MemberName { target_id: 20, member: 0, name: "lights".to_owned() });
+
+        let mut structs = BTreeMap::new();
+        assert_eq!(reflect_struct(&spirv, &block, &ident("Light"), Std::Std140, &mut structs).unwrap(), 64);
+        assert_eq!(structs.keys().collect::<Vec<_>>(), vec!["Light", "LightPadded"]);
+        assert!(structs["Light"].to_string().contains("pub lights : [ LightPadded ; 2usize ]"));
+
+        // Another block cannot take the name of a generated struct.
+        let error = reflect_struct(&spirv, &block, &ident("LightPadded"), Std::Std140, &mut structs).unwrap_err();
+        assert_eq!(error.to_string(), "LightPadded is also the name of a struct of the shader, choose another name");
+    }
+
+    fn reflect_error(spirv: &Spirv, block: &Type) -> String {
+        reflect_struct(spirv, block, &ident("Data"), Std::Std430, &mut BTreeMap::new()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reflect_missing_decorations() {
+        // The members of Lights have no names, and the element of lights no offsets.
+        let (spirv, block) = lights_block(vec![vec3(), vec3()], &[0]);
+        assert_eq!(reflect_error(&spirv, &block), "member member1 of an element of member member0 of Data does not have an offset in the shader");
+
+        let spirv = Spirv { instructions: offset_decorations(20, &[0]) };
+        let colors = Type::Array { element: Box::new(vec3()), length: 4, stride: None };
+        let block = Type::Struct { id: 20, members: vec![colors] };
+        assert_eq!(reflect_error(&spirv, &block), "member member0 of Data does not have an array stride in the shader");
+
+        let mat3 = Type::Matrix { column: Box::new(vec3()), count: 3 };
+        let block = Type::Struct { id: 20, members: vec![mat3] };
+        assert_eq!(reflect_error(&spirv, &block), "member member0 of Data does not have a matrix stride in the shader");
+    }
+
+    #[test]
+    fn row_major_matrix() {
+        // uniform Data { layout(row_major) mat3 transform; }
+        let mut instructions = offset_decorations(20, &[0]);
+        instructions.push(Instruction::MemberDecorate { target_id: 20, member: 0, decoration: DECORATION_MATRIX_STRIDE, params: vec![16] });
+        instructions.push(Instruction::MemberDecorate { target_id: 20, member: 0, decoration: DECORATION_ROW_MAJOR, params: vec![] });
+        let spirv = Spirv { instructions };
+        let block = Type::Struct { id: 20, members: vec![Type::Matrix { column: Box::new(vec3()), count: 3 }] };
+        assert_eq!(reflect_error(&spirv, &block), "member member0 of Data is row major in the shader, which is not supported");
+
+        let members = [(ident("transform"), GlslType::parse("mat3", &[]).unwrap())];
+        let error = check_block(&spirv, &block, &ident("Data"), &members, None, Std::Std140).unwrap_err();
+        assert_eq!(error.to_string(), "field transform of Data is row major in the shader, which is not supported");
+    }
+
+    #[test]
+    fn reflect_unsupported_types() {
+        let spirv = Spirv { instructions: offset_decorations(20, &[0]) };
+        let block = Type::Struct { id: 20, members: vec![Type::Int { width: 64, signed: true }] };
+        assert_eq!(reflect_error(&spirv, &block), "member member0 of Data is int64_t in the shader, which is not supported");
+
+        let half = Type::Vector { component: Box::new(Type::Float { width: 16 }), count: 2 };
+        let block = Type::Struct { id: 20, members: vec![half] };
+        assert_eq!(reflect_error(&spirv, &block), "member member0 of Data is vector of 2 float16_t in the shader, which is not supported");
+
+        assert_eq!(reflect_error(&spirv, &float()), "the block of Data is not a struct");
+    }
+
+    #[test]
+    fn parse_builtin_types() {
+        assert_eq!(GlslType::parse("float", &[]), Some(GlslType::Scalar(Scalar::Float)));
+        assert_eq!(GlslType::parse("bool", &[]), Some(GlslType::Scalar(Scalar::Uint)));
+        assert_eq!(GlslType::parse("dvec3", &[]), Some(GlslType::Vector(Scalar::Double, 3)));
+        assert_eq!(GlslType::parse("bvec2", &[]), Some(GlslType::Vector(Scalar::Uint, 2)));
+        assert_eq!(GlslType::parse("mat4", &[]), Some(GlslType::Matrix { scalar: Scalar::Float, columns: 4, rows: 4 }));
+        assert_eq!(GlslType::parse("dmat3x2", &[]), Some(GlslType::Matrix { scalar: Scalar::Double, columns: 3, rows: 2 }));
+        assert_eq!(GlslType::parse("vec5", &[]), None);
+        assert_eq!(GlslType::parse("mat2x", &[]), None);
+        assert_eq!(GlslType::parse("half", &[]), None);
+    }
+
+    #[test]
+    fn parse_arrays() {
+        let vec4 = GlslType::Vector(Scalar::Float, 4);
+        assert_eq!(GlslType::parse("vec4[8]", &[]), Some(GlslType::Array(Box::new(vec4.clone()), 8)));
+
+        // The first size is the outermost array.
+        let inner = GlslType::Array(Box::new(vec4), 2);
+        assert_eq!(GlslType::parse("vec4[4][2]", &[]), Some(GlslType::Array(Box::new(inner), 4)));
+
+        assert_eq!(GlslType::parse("vec4[0]", &[]), None);
+        assert_eq!(GlslType::parse("vec4[", &[]), None);
+        assert_eq!(GlslType::parse("vec4[a]", &[]), None);
+    }
+
+    #[test]
+    fn parse_structs() {
+        // These names start like a vector or a matrix.
+        let structs = [
+            decl("Format", &[("id", "uint")]),
+            decl("Animation", &[("time", "float")]),
+            decl("material", &[("color", "vec4"), ("format", "Format")]),
+            decl("vector", &[("x", "float")]),
+        ];
+
+        for expected in &["Format", "Animation", "material", "vector"] {
+            match GlslType::parse(expected, &structs) {
+                Some(GlslType::Struct { name, .. }) => assert_eq!(name, expected),
+                ty => panic!("{} parsed as {:?}", expected, ty),
+            }
+        }
+
+        match GlslType::parse("material", &structs) {
+            Some(GlslType::Struct { members, .. }) => assert_eq!(members[1].1, GlslType::parse("Format", &structs).unwrap()),
+            ty => panic!("material parsed as {:?}", ty),
+        }
+
+        assert_eq!(GlslType::parse("Unknown", &structs), None);
+    }
+
+    #[test]
+    fn parse_recursive_struct() {
+        let structs = [decl("Node", &[("next", "Node")])];
+        assert_eq!(GlslType::parse("Node", &structs), None);
+    }
+
+    #[test]
+    fn runtime_array_offset() {
+        let members = [(ident("a"), GlslType::parse("dvec2", &[]).unwrap()), (ident("b"), GlslType::parse("float", &[]).unwrap())];
+        let (fields, _) = std_layout(&members, Std::Std430);
+        assert_eq!(runtime_array_layout(&fields, &GlslType::parse("float", &[]).unwrap(), Std::Std430), (20, 4));
+        assert_eq!(runtime_array_layout(&fields, &GlslType::parse("vec4", &[]).unwrap(), Std::Std430), (32, 16));
+    }
+
+    #[test]
+    fn sanitized_idents() {
+        assert_eq!(rust_ident("Globals", || unreachable!()), ident("Globals"));
+        assert_eq!(rust_ident("type.Globals", || unreachable!()), ident("type_Globals"));
+        assert_eq!(rust_ident("", || "Struct4".to_owned()), ident("Struct4"));
+        assert_eq!(rust_ident("0", || unreachable!()), ident("_0"));
+    }
+}
@@ -7,38 +7,94 @@ extern crate quote;
 
 use proc_macro::TokenStream;
 use proc_macro2::Span;
-use syn::{Ident, LitInt, Token, LitStr, bracketed, braced, parenthesized};
+use syn::{Ident, LitBool, LitByteStr, Token, LitStr, braced, bracketed};
 use syn::parse::{Parse, ParseStream, Result};
+use std::cell::RefCell;
+use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
 mod descriptor;
+mod diagnostic;
+mod interface;
+mod layout;
+mod options;
 mod push_constants;
-use crate::descriptor::{generate_descriptor_layout, DescriptorInput};
+mod spirv;
+use crate::descriptor::{check_unique, generate_descriptor_layout, DescriptorInput};
+use crate::diagnostic::{Diagnostic, parse_diagnostics, generate_errors, generate_warnings};
 use crate::push_constants::{PushConstants, generate_pc};
+use crate::options::{ShaderOptions, parse_optimization, parse_target_env, parse_glsl_version};
+use crate::interface::{InterfaceElement, entries_from_elements, reflect_entries, generate_interface};
+use crate::spirv::{Spirv, GeometryInput, STORAGE_CLASS_INPUT, STORAGE_CLASS_OUTPUT,
+    EXECUTION_MODEL_VERTEX, EXECUTION_MODEL_TESSELLATION_CONTROL, EXECUTION_MODEL_TESSELLATION_EVALUATION,
+    EXECUTION_MODEL_GEOMETRY, EXECUTION_MODEL_FRAGMENT, EXECUTION_MODEL_GL_COMPUTE};
 
-// TODO Whatever I use at the moment. Other to be implemented later :)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum ShaderKind {
     Vertex,
     Fragment,
+    Compute,
+    Geometry,
+    TessellationControl,
+    TessellationEvaluation,
 }
 
 impl ShaderKind {
     
-    pub fn from_str(repr: &str) -> Self {
-        match repr {
+    const NAMES: &'static [&'static str] = &["vertex", "fragment", "compute", "geometry", "tessellation_control", "tessellation_evaluation"];
+
+    pub fn from_str(repr: &str) -> Option<Self> {
+        let kind = match repr {
             "fragment" => ShaderKind::Fragment,
             "vertex" => ShaderKind::Vertex,
-            _ => panic!(format!("Shader kind {} not supported yet.", repr))
+            "compute" => ShaderKind::Compute,
+            "geometry" => ShaderKind::Geometry,
+            "tessellation_control" => ShaderKind::TessellationControl,
+            "tessellation_evaluation" => ShaderKind::TessellationEvaluation,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+
+    /// Inverse of `from_str`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ShaderKind::Vertex => "vertex",
+            ShaderKind::Fragment => "fragment",
+            ShaderKind::Compute => "compute",
+            ShaderKind::Geometry => "geometry",
+            ShaderKind::TessellationControl => "tessellation_control",
+            ShaderKind::TessellationEvaluation => "tessellation_evaluation",
         }
     }
 
+    /// Stage of an entry point of a SPIR-V module.
+    pub fn from_execution_model(execution_model: u32) -> Option<Self> {
+        let kind = match execution_model {
+            EXECUTION_MODEL_VERTEX => ShaderKind::Vertex,
+            EXECUTION_MODEL_TESSELLATION_CONTROL => ShaderKind::TessellationControl,
+            EXECUTION_MODEL_TESSELLATION_EVALUATION => ShaderKind::TessellationEvaluation,
+            EXECUTION_MODEL_GEOMETRY => ShaderKind::Geometry,
+            EXECUTION_MODEL_FRAGMENT => ShaderKind::Fragment,
+            EXECUTION_MODEL_GL_COMPUTE => ShaderKind::Compute,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+
     pub fn get_shaderc_kind(&self) -> shaderc::ShaderKind {
 
         match *self {
             ShaderKind::Vertex => shaderc::ShaderKind::Vertex,
             ShaderKind::Fragment => shaderc::ShaderKind::Fragment,
+            ShaderKind::Compute => shaderc::ShaderKind::Compute,
+            ShaderKind::Geometry => shaderc::ShaderKind::Geometry,
+            ShaderKind::TessellationControl => shaderc::ShaderKind::TessControl,
+            ShaderKind::TessellationEvaluation => shaderc::ShaderKind::TessEvaluation,
         }
     }
 
@@ -51,43 +107,304 @@ impl ShaderKind {
             },
             ShaderKind::Fragment => {
                 quote!(ShaderStages { fragment: true, ..ShaderStages::none() })    
+            },
+            ShaderKind::Compute => {
+                quote!(ShaderStages { compute: true, ..ShaderStages::none() })
+            },
+            ShaderKind::Geometry => {
+                quote!(ShaderStages { geometry: true, ..ShaderStages::none() })
+            },
+            ShaderKind::TessellationControl => {
+                quote!(ShaderStages { tessellation_control: true, ..ShaderStages::none() })
+            },
+            ShaderKind::TessellationEvaluation => {
+                quote!(ShaderStages { tessellation_evaluation: true, ..ShaderStages::none() })
             }
         }
     }
 
-    pub fn generate_graphic_shader_type(&self) -> proc_macro2::TokenStream {
-        match *self {
+    /// Geometry shaders also need the input primitive, which is read from the
+    /// execution modes of the compiled shader.
+    pub fn generate_graphic_shader_type(&self, spirv: &Spirv, entry_name: &str) -> std::result::Result<proc_macro2::TokenStream, String> {
+        Ok(match *self {
             ShaderKind::Vertex => {
                 quote!(GraphicsShaderType::Vertex)
             },
             ShaderKind::Fragment => {
                 quote!(GraphicsShaderType::Fragment)    
+            },
+            ShaderKind::Geometry => {
+                let input = spirv.geometry_input(entry_name)
+                    .ok_or_else(|| format!("the geometry shader {} does not declare its input primitive", entry_name))?;
+                let mode = match input {
+                    GeometryInput::Points => quote!(Points),
+                    GeometryInput::Lines => quote!(Lines),
+                    GeometryInput::LinesWithAdjacency => quote!(LinesWithAdjacency),
+                    GeometryInput::Triangles => quote!(Triangles),
+                    GeometryInput::TrianglesWithAdjacency => quote!(TrianglesWithAdjacency),
+                };
+                quote!(GraphicsShaderType::Geometry(GeometryShaderExecutionMode::#mode))
+            },
+            ShaderKind::TessellationControl => {
+                quote!(GraphicsShaderType::TessellationControl)
+            },
+            ShaderKind::TessellationEvaluation => {
+                quote!(GraphicsShaderType::TessellationEvaluation)
+            },
+            ShaderKind::Compute => unreachable!("Compute shaders are not part of the graphics pipeline"),
+        })
+    }
+
+    /// Inputs of these stages are arrays with one element per vertex.
+    pub fn has_arrayed_inputs(&self) -> bool {
+        match *self {
+            ShaderKind::Geometry | ShaderKind::TessellationControl | ShaderKind::TessellationEvaluation => true,
+            _ => false,
+        }
+    }
+
+    /// Tessellation control shaders write one output per vertex of the patch.
+    pub fn has_arrayed_outputs(&self) -> bool {
+        match *self {
+            ShaderKind::TessellationControl => true,
+            _ => false,
+        }
+    }
+
+    /// Name of the variant in shaderc::ShaderKind. Used by the generated code
+    /// to recompile the shader at runtime.
+    pub fn generate_shaderc_kind(&self) -> proc_macro2::TokenStream {
+        match *self {
+            ShaderKind::Vertex => quote!(shaderc::ShaderKind::Vertex),
+            ShaderKind::Fragment => quote!(shaderc::ShaderKind::Fragment),
+            ShaderKind::Compute => quote!(shaderc::ShaderKind::Compute),
+            ShaderKind::Geometry => quote!(shaderc::ShaderKind::Geometry),
+            ShaderKind::TessellationControl => quote!(shaderc::ShaderKind::TessControl),
+            ShaderKind::TessellationEvaluation => quote!(shaderc::ShaderKind::TessEvaluation),
+        }
+    }
+}
+
+/// The parsers of the macro input return these errors instead of panicking,
+/// so the compiler points at the offending key.
+fn unexpected_key(key: &Ident, expected: &[&str]) -> syn::Error {
+    syn::Error::new_spanned(key, format!("unexpected key `{}`, expected one of: {}", key, expected.join(", ")))
+}
+
+fn duplicate_key(key: &Ident) -> syn::Error {
+    syn::Error::new_spanned(key, format!("`{}` is defined twice", key))
+}
+
+fn missing_key(span: Span, key: &str) -> syn::Error {
+    syn::Error::new(span, format!("missing `{}`", key))
+}
+
+/// Explicit list of stages, e.g. `stages: [vertex, fragment]`. The names are
+/// the fields of vulkano's `ShaderStages`.
+struct Stages(Vec<Ident>);
+
+impl Parse for Stages {
+
+    fn parse(input: ParseStream) -> Result<Self> {
+        let in_brackets;
+        bracketed!(in_brackets in input);
+
+        let mut stages = vec![];
+        while !in_brackets.is_empty() {
+            let stage: Ident = in_brackets.parse()?;
+            if ShaderKind::from_str(&stage.to_string()).is_none() {
+                return Err(syn::Error::new_spanned(&stage, format!("unknown shader stage `{}`, expected one of: {}",
+                                                                   stage, ShaderKind::NAMES.join(", "))));
+            }
+            stages.push(stage);
+
+            if !in_brackets.is_empty() {
+                in_brackets.parse::<Token![,]>()?;
             }
         }
+
+        Ok(Stages(stages))
     }
 }
 
-struct MacroInput {
-    path: String,
-    kind: ShaderKind,
-    input_desc: Vec<InterfaceElement>,
-    output_desc: Vec<InterfaceElement>,
+impl Stages {
+
+    pub fn contains(&self, kind: ShaderKind) -> bool {
+        self.0.iter().any(|stage| ShaderKind::from_str(&stage.to_string()) == Some(kind))
+    }
+
+    pub fn generate_shaderstages(&self) -> proc_macro2::TokenStream {
+        let stages = &self.0;
+        quote!(ShaderStages { #( #stages: true, )* ..ShaderStages::none() })
+    }
+}
+
+/// Where the shader comes from.
+enum Source {
+    // GLSL file, or SPIR-V already compiled when it ends with `.spv`.
+    Path(LitStr),
+    // GLSL written in the macro input. There is no file to reload.
+    Inline(LitStr),
+}
+
+/// Name given to the compiler for inline GLSL. It is in the crate directory so
+/// `#include "file"` is relative to the crate.
+const INLINE_NAME: &str = "<inline>";
+
+impl Source {
+
+    /// The literal the errors point at.
+    fn literal(&self) -> &LitStr {
+        match self {
+            Source::Path(path) => path,
+            Source::Inline(src) => src,
+        }
+    }
+
+    fn is_precompiled(&self) -> bool {
+        match self {
+            Source::Path(path) => path.value().ends_with(".spv"),
+            Source::Inline(_) => false,
+        }
+    }
 
-    // The size of each push constant range.
+    fn file_path(&self) -> PathBuf {
+        match self {
+            Source::Path(path) => resolve_path(&path.value()),
+            Source::Inline(_) => resolve_path(INLINE_NAME),
+        }
+    }
+}
+
+struct MacroInput {
+    source: Source,
+    // Required for GLSL. For SPIR-V, only the entry points of this kind are used.
+    kind: Option<ShaderKind>,
+    // `main` for GLSL and all the entry points of the module for SPIR-V.
+    entry_point: Option<LitStr>,
+    // When not given, the interface is reflected from the shader.
+    input_desc: Option<Vec<InterfaceElement>>,
+    output_desc: Option<Vec<InterfaceElement>>,
+
+    // Name and fields of the push constants. Reflected from the shader when not given.
     push_constants: Option<PushConstants>,
     descriptors: Vec<DescriptorInput>,
+
+    // Fail the build when the GLSL compiler has warnings.
+    warnings_as_errors: bool,
+
+    // Directories searched by `#include`, relative to the crate like `path`.
+    include_dirs: Vec<LitStr>,
+
+    // `#define` given to the shader, and the variants that add their own.
+    // There is one generated module per variant. A variant skips the
+    // descriptors and push constants that its shader does not use, but each
+    // of them has to be used by one of the variants.
+    defines: Vec<(String, String)>,
+    variants: Vec<Variant>,
+
+    // Optimization, debug info... Depend on the profile when not given.
+    options: ShaderOptions,
+}
+
+/// Parse `{ SHADOWS: "1", MAX_LIGHTS: "8" }`.
+fn parse_defines(input: ParseStream) -> Result<Vec<(String, String)>> {
+    let mut defines: Vec<(String, String)> = vec![];
+    let in_braces;
+    braced!(in_braces in input);
+
+    while !in_braces.is_empty() {
+        let name: Ident = in_braces.parse()?;
+        in_braces.parse::<Token![:]>()?;
+        let value: LitStr = in_braces.parse()?;
+
+        if defines.iter().any(|(defined, _)| name == defined) {
+            return Err(duplicate_key(&name));
+        }
+        defines.push((name.to_string(), value.value()));
+
+        if !in_braces.is_empty() {
+            in_braces.parse::<Token![,]>()?;
+        }
+    }
+
+    Ok(defines)
+}
+
+/// One permutation of the shader, e.g. `{ name: shadows, defines: { SHADOWS: "1" } }`.
+/// The name is the name of the generated module.
+struct Variant {
+    name: Ident,
+    defines: Vec<(String, String)>,
 }
 
+impl Parse for Variant {
+
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut name = None;
+        let mut defines = None;
+        let span = input.cursor().span();
+        let in_braces;
+        braced!(in_braces in input);
+
+        while !in_braces.is_empty() {
+            let key: Ident = in_braces.parse()?;
+            in_braces.parse::<Token![:]>()?;
+
+            match key.to_string().as_ref() {
+                "name" => {
+                    if name.is_some() {
+                        return Err(duplicate_key(&key));
+                    }
+
+                    name = Some(in_braces.parse::<Ident>()?);
+                },
+                "defines" => {
+                    if defines.is_some() {
+                        return Err(duplicate_key(&key));
+                    }
+
+                    defines = Some(parse_defines(&in_braces)?);
+                },
+                _ => return Err(unexpected_key(&key, &["name", "defines"])),
+            }
+
+            if !in_braces.is_empty() {
+                in_braces.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(Variant {
+            name: name.ok_or_else(|| missing_key(span, "name"))?,
+            defines: defines.unwrap_or_default(),
+        })
+    }
+}
+
+/// Keys that cannot be used with a `.spv` file.
+const COMPILER_KEYS: &[&str] = &["defines", "variants", "include_dirs", "optimization", "debug_info", "glsl_version", "warnings_as_errors"];
+
 impl Parse for MacroInput {
 
     fn parse(input: ParseStream) -> Result<Self> {
 
-        let mut path = None;
+        let mut source = None;
         let mut kind = None;
-        let mut input_desc = Vec::new();
-        let mut output_desc = Vec::new();
+        let mut entry_point = None;
+        let mut input_desc = None;
+        let mut output_desc = None;
         let mut push_constants = None;
-        let mut descriptors = Vec::new();
+        let mut descriptors = None;
+        let mut warnings_as_errors = None;
+        let mut include_dirs = None;
+        let mut defines = None;
+        let mut variants = None;
+        let mut optimization = None;
+        let mut debug_info = None;
+        let mut target_env = None;
+        let mut glsl_version = None;
+        // Keys that only change how GLSL is compiled.
+        let mut compiler_keys = vec![];
 
 
         while !input.is_empty() {
@@ -96,74 +413,195 @@ impl Parse for MacroInput {
             // kind: "....",
             let name: Ident = input.parse()?;
             input.parse::<Token![:]>()?;
+            if COMPILER_KEYS.contains(&name.to_string().as_ref()) {
+                compiler_keys.push(name.clone());
+            }
             match name.to_string().as_ref() {
-                "path" => {
-                    if path.is_some() {
-                        panic!("Only one path can be defined");
+                "path" | "src" => {
+                    if source.is_some() {
+                        return Err(syn::Error::new_spanned(&name, "only one of `path` and `src` can be defined"));
                     }
 
-                    let path_value: LitStr = input.parse()?;
-                    path = Some(path_value);
+                    let value: LitStr = input.parse()?;
+                    source = Some(if name == "path" { Source::Path(value) } else { Source::Inline(value) });
                 },
                 "kind" => {
                     if kind.is_some() {
-                        panic!("Only one kind can be defined");
+                        return Err(duplicate_key(&name));
                     }
 
                     let kind_value: LitStr = input.parse()?;
-                    kind = Some(ShaderKind::from_str(kind_value.value().as_str()));
+                    kind = Some(ShaderKind::from_str(kind_value.value().as_str()).ok_or_else(|| {
+                        syn::Error::new_spanned(&kind_value, format!("unknown shader kind `{}`, expected one of: {}",
+                                                                     kind_value.value(), ShaderKind::NAMES.join(", ")))
+                    })?);
+                },
+                "entry_point" => {
+                    if entry_point.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    entry_point = Some(input.parse::<LitStr>()?);
                 },
                 "input" => {
+                    if input_desc.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    let mut elements = Vec::new();
                     let in_brackets;
                     bracketed!(in_brackets in input);
 
                     while !in_brackets.is_empty() {
                         let input_el: InterfaceElement = in_brackets.parse()?;
 
-                        input_desc.push(input_el);
+                        elements.push(input_el);
 
                         if !in_brackets.is_empty() {
                             in_brackets.parse::<Token![,]>()?;
                         }
                     }
 
+                    input_desc = Some(elements);
+
                 },
                 "output" => {
+                    if output_desc.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    let mut elements = Vec::new();
                     let in_brackets;
                     bracketed!(in_brackets in input);
 
                     while !in_brackets.is_empty() {
                         let output_el: InterfaceElement = in_brackets.parse()?;
 
-                        output_desc.push(output_el);
+                        elements.push(output_el);
 
                         if !in_brackets.is_empty() {
                             in_brackets.parse::<Token![,]>()?;
                         }
                     }
+
+                    output_desc = Some(elements);
                 },
                 "push_constants" => {
 
                     if push_constants.is_some() {
-                        panic!("Only one push constant can be defined");
+                        return Err(duplicate_key(&name));
                     }
 
                     let pc: PushConstants = input.parse()?;
                     push_constants = Some(pc);
                 },
                 "descriptors" => {
+                    if descriptors.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    let mut list: Vec<DescriptorInput> = vec![];
+                    let in_brackets;
+                    bracketed!(in_brackets in input);
+
+                    while !in_brackets.is_empty() {
+                        let desc: DescriptorInput = in_brackets.parse()?;
+                        check_unique(&list, &desc)?;
+                        list.push(desc);
+
+                        if !in_brackets.is_empty() {
+                            in_brackets.parse::<Token![,]>()?;
+                        }
+                    }
+
+                    descriptors = Some(list);
+                },
+                "warnings_as_errors" => {
+                    if warnings_as_errors.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    warnings_as_errors = Some(input.parse::<LitBool>()?.value);
+                },
+                "include_dirs" => {
+                    if include_dirs.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    let mut dirs = vec![];
                     let in_brackets;
                     bracketed!(in_brackets in input);
 
                     while !in_brackets.is_empty() {
-                        descriptors.push(in_brackets.parse::<DescriptorInput>()?);
+                        dirs.push(in_brackets.parse::<LitStr>()?);
                         if !in_brackets.is_empty() {
                             in_brackets.parse::<Token![,]>()?;
                         }
                     }
 
+                    include_dirs = Some(dirs);
+                },
+                "defines" => {
+                    if defines.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    defines = Some(parse_defines(input)?);
+                },
+                "variants" => {
+                    if variants.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    let mut list: Vec<Variant> = vec![];
+                    let in_brackets;
+                    bracketed!(in_brackets in input);
+
+                    while !in_brackets.is_empty() {
+                        let variant: Variant = in_brackets.parse()?;
+                        if list.iter().any(|v| v.name == variant.name) {
+                            return Err(syn::Error::new_spanned(&variant.name, format!("variant `{}` is defined twice", variant.name)));
+                        }
+                        list.push(variant);
+
+                        if !in_brackets.is_empty() {
+                            in_brackets.parse::<Token![,]>()?;
+                        }
+                    }
+
+                    variants = Some(list);
+                },
+                "optimization" => {
+                    if optimization.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    optimization = Some(parse_optimization(input)?);
                 },
-                _ => panic!("Unexpected value"),
+                "debug_info" => {
+                    if debug_info.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    debug_info = Some(input.parse::<LitBool>()?.value);
+                },
+                "target_env" => {
+                    if target_env.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    target_env = Some(parse_target_env(input)?);
+                },
+                "glsl_version" => {
+                    if glsl_version.is_some() {
+                        return Err(duplicate_key(&name));
+                    }
+
+                    glsl_version = Some(parse_glsl_version(input)?);
+                },
+                _ => return Err(unexpected_key(&name, &["path", "src", "kind", "entry_point", "input", "output", "push_constants", "descriptors",
+                                                        "warnings_as_errors", "include_dirs", "defines", "variants",
+                                                        "optimization", "debug_info", "target_env", "glsl_version"])),
             }
 
             if !input.is_empty() {
@@ -171,218 +609,631 @@ impl Parse for MacroInput {
             }
         }
 
+        let source = source.ok_or_else(|| missing_key(Span::call_site(), "path` or `src"))?;
+        if kind.is_none() && !source.is_precompiled() {
+            return Err(missing_key(Span::call_site(), "kind"));
+        }
+        if let (true, Some(key)) = (source.is_precompiled(), compiler_keys.first()) {
+            return Err(syn::Error::new_spanned(key, format!("`{}` cannot be used with a precompiled SPIR-V module", key)));
+        }
+
         Ok(MacroInput {
-            kind: kind.expect("Cannot find shader kind"),
-            path: path.expect("Cannot find shader path").value(),
+            kind,
+            entry_point,
+            source,
             input_desc,
             output_desc,
             push_constants,
-            descriptors,
+            descriptors: descriptors.unwrap_or_default(),
+            warnings_as_errors: warnings_as_errors.unwrap_or(false),
+            include_dirs: include_dirs.unwrap_or_default(),
+            defines: defines.unwrap_or_default(),
+            variants: variants.unwrap_or_default(),
+            options: ShaderOptions::new(optimization, debug_info, target_env, glsl_version),
         })
     }
 }
 
-struct InterfaceElement {
-    format: Ident,
-    name: LitStr,
+/// Words of an entry point name, e.g. `vs_main` or `vsMain` give `vs` and `main`.
+fn name_words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() || (c.is_ascii_uppercase() && word.chars().last().map(|l| l.is_ascii_lowercase()).unwrap_or(false)) {
+            if !word.is_empty() {
+                words.push(word.clone());
+                word.clear();
+            }
+        }
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
 }
 
+/// Prefix of the types of an entry point, `vs_main` gives `VsMainInput`...
+fn camel_case(name: &str) -> String {
+    name_words(name).iter().map(|word| {
+        let mut chars = word.chars();
+        chars.next().map(|c| c.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase()).unwrap_or_default()
+    }).collect()
+}
 
-impl Parse for InterfaceElement {
+/// Name of the accessor of an entry point, `vsMain` gives `vs_main_entry_point`.
+fn snake_case(name: &str) -> String {
+    name_words(name).iter().map(|word| word.to_ascii_lowercase()).collect::<Vec<_>>().join("_")
+}
 
-    fn parse(input: ParseStream) -> Result<Self> {
-        let mut format = None;
-        let mut name = None;
-        let in_braces;
-        braced!(in_braces in input);
+/// Read a SPIR-V module and check that it can be parsed. Errors are returned
+/// as `compile_error!` pointing at the path.
+fn read_spirv(path: &LitStr, source_path: &Path) -> std::result::Result<Vec<u32>, proc_macro2::TokenStream> {
+    let file_name = source_path.to_string_lossy().into_owned();
+    let error = |message: String| generate_errors(path, &[Diagnostic::new(&file_name, message)]);
 
-        while !in_braces.is_empty() {
+    let mut bytes = vec![];
+    File::open(source_path)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|e| error(format!("cannot read the shader: {}", e)))?;
 
-            let ident: Ident = in_braces.parse()?;
-            in_braces.parse::<Token![:]>()?;
+    if bytes.len() % 4 != 0 {
+        return Err(error("the size of a SPIR-V module is a multiple of 4 bytes".to_owned()));
+    }
 
-            match ident.to_string().as_ref() {
-                "format" => {
-                    if format.is_some() {
-                        panic!("already has a format.");
-                    }
+    // The magic number tells the endianness of the module.
+    let words: Vec<u32> = bytes.chunks(4).map(|b| u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24).collect();
+    let words = match words.first() {
+        Some(0x0723_0203) => words,
+        Some(0x0302_2307) => words.into_iter().map(u32::swap_bytes).collect(),
+        _ => return Err(error("the file is not a SPIR-V module".to_owned())),
+    };
 
-                    let format_value: Ident = in_braces.parse()?;
-                    format = Some(format_value);
-                },
-                "name" => {
-                    if name.is_some() {
-                        panic!("Already has a name.");
-                    }
+    Spirv::parse(&words).map_err(error)?;
+    Ok(words)
+}
 
-                    let name_value: LitStr = in_braces.parse()?;
-                    name = Some(name_value);
-                },
-                _ => panic!("not expected"),
-            }
+/// Relative paths are relative to the crate using the macro, not to the
+/// directory rustc runs in.
+fn resolve_path(path: &str) -> PathBuf {
+    match env::var("CARGO_MANIFEST_DIR") {
+        Ok(dir) => Path::new(&dir).join(path),
+        Err(_) => PathBuf::from(path),
+    }
+}
 
-            if !in_braces.is_empty() {
-                in_braces.parse::<Token![,]>()?;
-            }
-        }
+/// `#include "file"` is looked up next to the file that includes it, then in
+/// the include directories. `#include <file>` only uses the include directories.
+///
+/// The generated `recompile` does the same, see `resolve_include` in `twshader`.
+fn resolve_include(requested: &str, include_type: shaderc::IncludeType, requesting: &str, include_dirs: &[PathBuf])
+    -> std::result::Result<shaderc::ResolvedInclude, String> {
 
-        Ok(Self {
-            format: format.expect("Cannot find Shader interface format"),
-            name: name.expect("Cannot find shader interface name"),
-         })
+    let mut candidates = vec![];
+    if include_type == shaderc::IncludeType::Relative {
+        candidates.push(Path::new(requesting).parent().unwrap_or_else(|| Path::new("")).join(requested));
+    }
+    candidates.extend(include_dirs.iter().map(|dir| dir.join(requested)));
+
+    for candidate in candidates {
+        if let Ok(content) = std::fs::read_to_string(&candidate) {
+            return Ok(shaderc::ResolvedInclude {
+                resolved_name: candidate.to_string_lossy().into_owned(),
+                content,
+            });
+        }
     }
+
+    Err(format!("cannot find {} in the include directories", requested))
 }
 
-fn generate_interface(struct_name: Ident, elements: &Vec<InterfaceElement>) -> proc_macro2::TokenStream {
-
-    let mut input_impl = vec!();
-    if elements.len() > 0 {
-        let mut current_num = (elements.len() - 1) as u16;
-        for (index, element) in elements.iter().enumerate() {
-
-            let index = index as u32;
-            let name = &element.name;
-            let format = &element.format;
-            let next_index = index + 1;
-            input_impl.push(quote!(
-                    if self.0 == #current_num {
-                        self.0 += 1;
-                        return Some(ShaderInterfaceDefEntry {
-                            location: #index..#next_index,
-                            format: Format::#format,
-                            name: Some(Cow::Borrowed(#name))
-                        })
-                    }
-            ));
+/// Result of the compilation of a shader.
+struct Compiled {
+    words: Vec<u32>,
+    warnings: proc_macro2::TokenStream,
+    // The shader and all the files it includes.
+    sources: Vec<PathBuf>,
+}
 
-            if current_num > 0 {
-                current_num = current_num - 1;
+/// Compile the shader to SPIR-V. Errors are returned as `compile_error!`
+/// pointing at the path or the inline source.
+fn compile(source: &Source,
+           shader_kind: ShaderKind,
+           entry_point: &str,
+           include_dirs: &[PathBuf],
+           defines: &[(String, String)],
+           shader_options: &ShaderOptions,
+           warnings_as_errors: bool)
+    -> std::result::Result<Compiled, proc_macro2::TokenStream> {
+
+    let path = source.literal();
+    let source_path = source.file_path();
+    let file_name = source_path.to_string_lossy().into_owned();
+    let error = |message: String| generate_errors(path, &[Diagnostic::new(&file_name, message)]);
+
+    // The lines of inline GLSL in the messages come from the macro input.
+    let (content, sources) = match source {
+        Source::Path(_) => {
+            let mut content = String::new();
+            File::open(&source_path)
+                .and_then(|mut f| f.read_to_string(&mut content))
+                .map_err(|e| error(format!("cannot read the shader: {}", e)))?;
+            (content, vec![source_path.clone()])
+        },
+        Source::Inline(src) => (src.value(), vec![]),
+    };
+    let with_source = |mut diagnostics: Vec<Diagnostic>| {
+        if let Source::Inline(_) = source {
+            for diagnostic in diagnostics.iter_mut() {
+                diagnostic.set_source(&file_name, &content);
             }
         }
+        diagnostics
+    };
+
+    let sources = RefCell::new(sources);
+    let mut options = shaderc::CompileOptions::new()
+        .ok_or_else(|| error("cannot create the shaderc compile options".to_owned()))?;
+    options.set_include_callback(|requested, include_type, requesting, _depth| {
+        let included = resolve_include(requested, include_type, requesting, include_dirs)?;
+        sources.borrow_mut().push(PathBuf::from(&included.resolved_name));
+        Ok(included)
+    });
+    for (name, value) in defines {
+        options.add_macro_definition(name, Some(value));
     }
+    shader_options.apply(&mut options);
+
+    let mut compiler = shaderc::Compiler::new()
+        .ok_or_else(|| error("cannot create the shaderc compiler".to_owned()))?;
+    let artifact = match compiler.compile_into_spirv(content.as_str(), shader_kind.get_shaderc_kind(), &file_name, entry_point, Some(&options)) {
+        Ok(artifact) => artifact,
+        Err(shaderc::Error::CompilationError(_, output)) => {
+            let diagnostics = parse_diagnostics(&output);
+            if diagnostics.is_empty() {
+                return Err(error(output));
+            }
+            return Err(generate_errors(path, &with_source(diagnostics)));
+        },
+        Err(e) => return Err(error(e.to_string())),
+    };
 
-    let mut iter_name = struct_name.to_string();
-    iter_name.push_str("Iter");
-    let iter_name = Ident::new(iter_name.as_str(), Span::call_site());
+    let warnings = with_source(parse_diagnostics(&artifact.get_warning_messages()));
+    if warnings_as_errors && !warnings.is_empty() {
+        return Err(generate_errors(path, &warnings));
+    }
 
-    let length = elements.len();
-    quote!(
-        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
-        pub struct #struct_name;
+    drop(options);
+    Ok(Compiled {
+        words: artifact.as_binary().to_vec(),
+        warnings: generate_warnings(path, &warnings),
+        sources: sources.into_inner(),
+    })
+}
 
-        unsafe impl ShaderInterfaceDef for #struct_name {
-            type Iter = #iter_name;
 
-            fn elements(&self) -> #iter_name {
-                #iter_name(0)
-            }
-        }
+#[proc_macro]
+pub fn twshader(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as MacroInput);
 
-        #[derive(Debug, Copy, Clone)]
-        pub struct #iter_name(u16);
-        impl Iterator for #iter_name {
-            type Item = ShaderInterfaceDefEntry;
+    let mut usage = Usage {
+        descriptors: vec![false; input.descriptors.len()],
+        push_constants: false,
+        reflected: 0,
+    };
 
-            #[inline]
-            fn next(&mut self) -> Option<Self::Item> {
-                #( #input_impl )*
-                None
-            }
+    if input.variants.is_empty() {
+        return generate_shader(&input, &input.defines, &mut usage).into();
+    }
 
-            #[inline]
-            fn size_hint(&self) -> (usize, Option<usize>) {
-                let len = #length - self.0 as usize;
-                (len, Some(len))
+    // The defines of a variant are added to the common ones, and replace
+    // them when they have the same name.
+    let modules: Vec<_> = input.variants.iter().map(|variant| {
+        let mut defines = input.defines.clone();
+        for (name, value) in variant.defines.iter() {
+            match defines.iter_mut().find(|(defined, _)| defined == name) {
+                Some(define) => define.1 = value.clone(),
+                None => defines.push((name.clone(), value.clone())),
             }
         }
 
-        impl ExactSizeIterator for #iter_name { }
+        let name = &variant.name;
+        let shader = generate_shader(&input, &defines, &mut usage);
+        quote!(
+            pub mod #name {
+                #shader
+            }
         )
-}
-
-
-
+    }).collect();
+
+    // A typo in a set or binding would otherwise be skipped by every variant.
+    // When a variant does not compile, its errors are enough.
+    let mut errors = vec![];
+    if usage.reflected == input.variants.len() {
+        for (desc, used) in input.descriptors.iter().zip(usage.descriptors.iter()) {
+            if !used {
+                errors.push(descriptor::unused_error(desc, "any variant").to_compile_error());
+            }
+        }
 
-fn compile(path: String, shader_kind: ShaderKind) -> Vec<u32> {
-    let mut f = File::open(&path).unwrap();
-    let mut content = String::new();
-    f.read_to_string(&mut content).unwrap();
+        if let Some(pc) = input.push_constants.as_ref().filter(|_| !usage.push_constants) {
+            errors.push(push_constants::unused_error(pc, "any variant").to_compile_error());
+        }
+    }
 
-    let mut compiler = shaderc::Compiler::new().unwrap();
-    compiler.compile_into_spirv(
-        content.as_str(),
-        shader_kind.get_shaderc_kind(),
-        &path, "main", None).unwrap().as_binary().to_vec()
+    let expanded = quote!(
+        #( #modules )*
+        #( #errors )*
+    );
+    expanded.into()
 }
 
+/// What the variants of the shader use from the macro input.
+struct Usage {
+    // One per descriptor of the macro input.
+    descriptors: Vec<bool>,
+    push_constants: bool,
+    // Number of variants that compiled and were reflected.
+    reflected: usize,
+}
 
-#[proc_macro]
-pub fn twshader(input: TokenStream) -> TokenStream {
-    let MacroInput { 
-        path,
+/// Compile the shader with the given defines and generate the `Shader` type
+/// and its layout. What the shader uses is added to `usage`.
+fn generate_shader(input: &MacroInput, defines: &[(String, String)], usage: &mut Usage) -> proc_macro2::TokenStream {
+    let MacroInput {
+        source,
         kind,
+        entry_point,
         input_desc,
         output_desc,
         push_constants,
-        descriptors } = syn::parse_macro_input!(input as MacroInput);
+        descriptors,
+        warnings_as_errors,
+        include_dirs,
+        options,
+        .. } = input;
+
+    let path = source.literal();
+    let source_path = source.file_path();
+    let absolute_include_dirs: Vec<_> = include_dirs.iter().map(|dir| resolve_path(&dir.value())).collect();
+    let error = |message: String| generate_errors(path, &[Diagnostic::new(&source_path.to_string_lossy(), message)]);
+    let glsl_entry_point = entry_point.as_ref().map(|name| name.value()).unwrap_or_else(|| "main".to_owned());
+
+    let (spirv, reflected_spirv, warnings, sources) = if source.is_precompiled() {
+        match read_spirv(path, &source_path) {
+            Ok(words) => (words.clone(), words, quote!(), vec![source_path.clone()]),
+            Err(errors) => return errors,
+        }
+    } else {
+        // Compile to SPIRV :D
+        let kind = kind.expect("The kind of a GLSL shader is checked when parsing");
+        let Compiled { words, warnings, sources } = match compile(source, kind, &glsl_entry_point, &absolute_include_dirs,
+                                                                  defines, options, *warnings_as_errors) {
+            Ok(compiled) => compiled,
+            Err(errors) => return errors,
+        };
+
+        // The reflection needs the names that an optimized build does not have.
+        let reflected_words = match options.for_reflection() {
+            Some(reflection_options) => match compile(source, kind, &glsl_entry_point, &absolute_include_dirs,
+                                                      defines, &reflection_options, false) {
+                Ok(compiled) => compiled.words,
+                Err(errors) => return errors,
+            },
+            None => words.clone(),
+        };
+
+        (words, reflected_words, warnings, sources)
+    };
+
+    // Cargo does not know the macro reads these files. Including them makes
+    // the crate rebuild when one of them changes.
+    let mut sources: Vec<_> = sources.iter().map(|source| source.to_string_lossy().into_owned()).collect();
+    sources.sort();
+    sources.dedup();
+    let tracked_sources = sources.iter().enumerate().map(|(index, source)| {
+        let name = Ident::new(&format!("_SHADER_SOURCE_{}", index), Span::call_site());
+        quote!(
+            #[allow(dead_code)]
+            const #name: &[u8] = include_bytes!(#source);
+        )
+    });
 
-    // Compile to SPIRV :D
-    let spirv = compile(path.clone(), kind);
-    let path = LitStr::new(&path, Span::call_site());
+    let parsed = match Spirv::parse(&reflected_spirv) {
+        Ok(parsed) => parsed,
+        Err(message) => return error(message),
+    };
+    if let Err(message) = push_constants::check_single_block(&parsed) {
+        return error(message);
+    }
 
-    let struct_name_in = Ident::new("MainInput", Span::call_site());
-    let in_interface = generate_interface(struct_name_in.clone(), &input_desc);
-    let struct_name_out = Ident::new("MainOutput", Span::call_site());
-    let out_interface = generate_interface(struct_name_out.clone(), &output_desc);
-    let (pc_impl, pc_struct_impl) = generate_pc(push_constants);
-    let (desc_impl, desc_struct_impl) = generate_descriptor_layout(descriptors);
+    usage.reflected += 1;
+    usage.push_constants |= push_constants::is_used(&parsed);
+    for (desc, used) in descriptors.iter().zip(usage.descriptors.iter_mut()) {
+        *used |= descriptor::is_used(desc, &parsed);
+    }
 
-    let shader_stage = kind.generate_shaderstage();
-    let graphic_shader_type = kind.generate_graphic_shader_type();
+    // A GLSL shader has one entry point. A SPIR-V module can have several, and
+    // `entry_point` and `kind` select some of them.
+    let mut entries = vec![];
+    for (name, execution_model) in parsed.entry_points() {
+        if entry_point.as_ref().map(|wanted| wanted.value() != name).unwrap_or(false) {
+            continue;
+        }
 
-    let shaderc_type = match kind.get_shaderc_kind() {
-        shaderc::ShaderKind::Vertex => {
-            Ident::new("Vertex", Span::call_site())
-        },
-        shaderc::ShaderKind::Fragment => {
-            Ident::new("Fragment", Span::call_site())
+        let entry_kind = match ShaderKind::from_execution_model(execution_model) {
+            Some(entry_kind) => entry_kind,
+            None => return error(format!("entry point {} has the unsupported execution model {}", name, execution_model)),
+        };
+        if kind.map(|kind| kind != entry_kind).unwrap_or(false) {
+            continue;
+        }
+
+        entries.push((name, entry_kind));
+    }
+
+    if entries.is_empty() {
+        return error("cannot find an entry point matching `entry_point` and `kind` in the module".to_owned());
+    }
+    if entries.len() > 1 && (input_desc.is_some() || output_desc.is_some()) {
+        return error("`input` and `output` cannot be used with several entry points, add an `entry_point`".to_owned());
+    }
+
+    // The descriptors and push constants are the ones of the whole module, so
+    // all the entry points have the same layout apart from the stage. Some of
+    // them can be behind an `#ifdef` that a variant does not define, they are
+    // checked once all the variants are generated.
+    let allow_unused = !input.variants.is_empty();
+    let kinds: Vec<_> = entries.iter().map(|(_, kind)| *kind).collect();
+    let (pc_impl, pc_struct_impl) = match generate_pc(push_constants.as_ref(), &parsed, &kinds, allow_unused) {
+        Ok(pc) => pc,
+        Err(e) => return e.to_compile_error(),
+    };
+    let (desc_impl, desc_struct_impl) = match generate_descriptor_layout(descriptors, &parsed, allow_unused) {
+        Ok(layout) => layout,
+        Err(e) => return e.to_compile_error(),
+    };
+
+    let mut entry_types = vec![];
+    let mut entry_points = vec![];
+    for (name, kind) in entries.iter() {
+        let kind = *kind;
+        let type_prefix = camel_case(name);
+        let struct_name_in = Ident::new(&format!("{}Input", type_prefix), Span::call_site());
+        let struct_name_out = Ident::new(&format!("{}Output", type_prefix), Span::call_site());
+        let layout_name = Ident::new(&format!("{}Layout", type_prefix), Span::call_site());
+        let accessor = Ident::new(&format!("{}_entry_point", snake_case(name)), Span::call_site());
+        let c_name = LitByteStr::new(format!("{}\0", name).as_bytes(), Span::call_site());
+
+        let input_entries = match input_desc {
+            Some(elements) => entries_from_elements(elements),
+            None => match reflect_entries(&parsed, name, STORAGE_CLASS_INPUT, kind.has_arrayed_inputs()) {
+                Ok(entries) => entries,
+                Err(message) => return error(message),
+            },
+        };
+        let output_entries = match output_desc {
+            Some(elements) => entries_from_elements(elements),
+            None => match reflect_entries(&parsed, name, STORAGE_CLASS_OUTPUT, kind.has_arrayed_outputs()) {
+                Ok(entries) => entries,
+                Err(message) => return error(message),
+            },
+        };
+
+        let in_interface = generate_interface(struct_name_in.clone(), &input_entries);
+        let out_interface = generate_interface(struct_name_out.clone(), &output_entries);
+        entry_types.push(quote!(
+            #in_interface
+            #out_interface
+
+            // This structure describes layout of this stage.
+            #[derive(Debug, Copy, Clone)]
+            pub struct #layout_name(ShaderStages);
+            unsafe impl PipelineLayoutDesc for #layout_name {
+                // Number of descriptor sets it takes.
+                #desc_impl
+                #pc_impl
+            }
+        ));
+
+        let shader_stage = kind.generate_shaderstage();
+        entry_points.push(match kind {
+            ShaderKind::Compute => {
+                let [x, y, z] = parsed.local_size(name);
+                let local_size = if name == "main" {
+                    Ident::new("LOCAL_SIZE", Span::call_site())
+                } else {
+                    Ident::new(&format!("{}_LOCAL_SIZE", snake_case(name).to_uppercase()), Span::call_site())
+                };
+                quote!(
+                    /// Workgroup size declared with `local_size_x/y/z` in the shader.
+                    pub const #local_size: [u32; 3] = [#x, #y, #z];
+
+                    pub fn #accessor(&self) -> vulkano::pipeline::shader::ComputeEntryPoint<(), #layout_name> {
+                        unsafe {
+                            self.module.compute_entry_point(
+                                CStr::from_bytes_with_nul_unchecked(#c_name),
+                                #layout_name(#shader_stage)
+                            ) }
+                    }
+                )
+            },
+            _ => {
+                let graphic_shader_type = match kind.generate_graphic_shader_type(&parsed, name) {
+                    Ok(graphic_shader_type) => graphic_shader_type,
+                    Err(message) => return error(message),
+                };
+                quote!(
+                    pub fn #accessor(&self) -> vulkano::pipeline::shader::GraphicsEntryPoint<(), #struct_name_in, #struct_name_out, #layout_name> {
+                        unsafe {
+                            self.module.graphics_entry_point(
+                                CStr::from_bytes_with_nul_unchecked(#c_name),
+                                #struct_name_in,
+                                #struct_name_out,
+                                #layout_name(#shader_stage),
+                                #graphic_shader_type
+                            ) }
+                    }
+                )
+            },
+        });
+    }
+
+    let recompile_body = if let Source::Inline(_) = source {
+        quote!(
+            let _ = device;
+            Err("the shader was compiled from inline source, there is no file to recompile".into())
+        )
+    } else if source.is_precompiled() {
+        quote!(
+            let mut spirv = vec![];
+            File::open(&self.source_path)?.read_to_end(&mut spirv)?;
+
+            unsafe {
+                self.module = ShaderModule::new(device, &spirv)?;
+            }
+            Ok(())
+        )
+    } else {
+        let shaderc_type = entries[0].1.generate_shaderc_kind();
+        let (define_names, define_values): (Vec<_>, Vec<_>) = defines.iter().cloned().unzip();
+        let apply_options = options.generate_apply();
+        quote!(
+            let mut f = File::open(&self.source_path)?;
+            let mut content = String::new();
+            f.read_to_string(&mut content)?;
+
+            let include_dirs = &self.include_dirs;
+            let mut options = shaderc::CompileOptions::new().ok_or("cannot create the shaderc compile options")?;
+            options.set_include_callback(|requested, include_type, requesting, _depth| {
+                Self::resolve_include(requested, include_type, requesting, include_dirs)
+            });
+            let defines: &[(&str, &str)] = &[ #( (#define_names, #define_values) ),* ];
+            for (name, value) in defines {
+                options.add_macro_definition(name, Some(value));
+            }
+            #apply_options
+
+            let mut compiler = shaderc::Compiler::new().ok_or("cannot create the shaderc compiler")?;
+            let spirv = compiler.compile_into_spirv(
+                content.as_str(),
+                #shaderc_type,
+                &self.source_path.to_string_lossy(), #glsl_entry_point, Some(&options))?;
+
+            let spirv = spirv.as_binary();
+
+            //// then, change the module.
+            unsafe {
+                self.module = ShaderModule::from_words(device, &spirv)?;
+            }
+            Ok(())
+        )
+    };
+
+    // Inline source has no file to reload, so the `Shader` only keeps the module.
+    let (file_fields, file_init, file_methods) = match source {
+        Source::Inline(_) => (quote!(), quote!(), quote!()),
+        Source::Path(_) => {
+            let absolute_path = source_path.to_string_lossy().into_owned();
+            let absolute_include_dirs: Vec<_> = absolute_include_dirs.iter().map(|dir| dir.to_string_lossy().into_owned()).collect();
+            (
+                quote!(
+                    // GLSL or SPIR-V file read by `recompile`.
+                    source_path: PathBuf,
+                    // Directories searched by `#include` in `recompile`.
+                    include_dirs: Vec<PathBuf>,
+                ),
+                quote!(
+                    source_path: Self::default_source_path(),
+                    include_dirs: Self::default_include_dirs(),
+                ),
+                quote!(
+        /// The file the shader was compiled from. When the binary does not run
+        /// where it was built, `TWSHADER_SOURCE_DIR` replaces the crate directory.
+        pub fn default_source_path() -> PathBuf {
+            match std::env::var_os("TWSHADER_SOURCE_DIR") {
+                Some(dir) => Path::new(&dir).join(#path),
+                None => PathBuf::from(#absolute_path),
+            }
+        }
+
+        /// GLSL or SPIR-V file read by `recompile`.
+        pub fn source_path(&self) -> &Path {
+            &self.source_path
+        }
+
+        pub fn set_source_path<P: AsRef<Path>>(&mut self, path: P) {
+            self.source_path = path.as_ref().to_path_buf();
+        }
+
+        /// The `include_dirs` of the macro, found like `default_source_path`.
+        pub fn default_include_dirs() -> Vec<PathBuf> {
+            let relative: &[&str] = &[ #( #include_dirs ),* ];
+            let absolute: &[&str] = &[ #( #absolute_include_dirs ),* ];
+            match std::env::var_os("TWSHADER_SOURCE_DIR") {
+                Some(dir) => relative.iter().map(|include_dir| Path::new(&dir).join(include_dir)).collect(),
+                None => absolute.iter().map(PathBuf::from).collect(),
+            }
+        }
+
+        /// Directories searched by `#include` in `recompile`.
+        pub fn include_dirs(&self) -> &[PathBuf] {
+            &self.include_dirs
+        }
+
+        pub fn set_include_dirs(&mut self, include_dirs: Vec<PathBuf>) {
+            self.include_dirs = include_dirs;
+        }
+                ),
+            )
         },
-        _ => panic!("Not supported yet."),
     };
 
     let expanded = quote!(
         //use shaderc::{Compiler, CompileOptions};
+        // Which of these are used depends on the stages, descriptors and push constants.
+        #[allow(unused_imports)]
         use std::fs::File;
+        #[allow(unused_imports)]
         use std::io::Read;
+        #[allow(unused_imports)]
         use vulkano::format::Format;
+        #[allow(unused_imports)]
         use std::borrow::Cow;
+        #[allow(unused_imports)]
         use vulkano::descriptor::descriptor::{DescriptorDescTy, DescriptorDesc, DescriptorBufferDesc, DescriptorImageDesc, DescriptorImageDescArray, DescriptorImageDescDimensions};
+        #[allow(unused_imports)]
         use std::ffi::CStr;
-        use vulkano::pipeline::shader::{GraphicsShaderType, ShaderInterfaceDef, ShaderInterfaceDefEntry, ShaderModule};
+        #[allow(unused_imports)]
+        use vulkano::pipeline::shader::{GraphicsShaderType, GeometryShaderExecutionMode, ShaderInterfaceDef, ShaderInterfaceDefEntry, ShaderModule};
+        #[allow(unused_imports)]
         use vulkano::descriptor::descriptor::ShaderStages;
+        #[allow(unused_imports)]
         use vulkano::descriptor::pipeline_layout::PipelineLayoutDesc;
+        #[allow(unused_imports)]
         use vulkano::descriptor::pipeline_layout::PipelineLayoutDescPcRange;
+        #[allow(unused_imports)]
         use vulkano::pipeline::shader::GraphicsEntryPointAbstract;
 
+        #[allow(unused_imports)]
         use vulkano::device::Device;
+        #[allow(unused_imports)]
         use std::sync::Arc;
+        #[allow(unused_imports)]
+        use std::path::{Path, PathBuf};
 
+        #warnings
+        #( #tracked_sources )*
 
-        #in_interface
-        #out_interface
-
-        // This structure describes layout of this stage.
-        #[derive(Debug, Copy, Clone)]
-        pub struct MainLayout(ShaderStages);
-        unsafe impl PipelineLayoutDesc for MainLayout {
-            // Number of descriptor sets it takes.
-            #desc_impl
-            #pc_impl
-        }
+        #( #entry_types )*
 
 
         pub struct Shader {
             module: Arc<ShaderModule>,
+            #file_fields
         }
 
 
@@ -394,41 +1245,45 @@ pub fn twshader(input: TokenStream) -> TokenStream {
                 unsafe {
                     Ok(
                         Shader {
-                            module: ShaderModule::from_words(device, &words)?
+                            module: ShaderModule::from_words(device, &words)?,
+                            #file_init
                         })
                 }
             }
 
-            pub fn main_entry_point(&self) -> vulkano::pipeline::shader::GraphicsEntryPoint<(), MainInput, MainOutput, MainLayout> {
-                unsafe { 
-                    self.module.graphics_entry_point(
-                        CStr::from_bytes_with_nul_unchecked(b"main\0"),
-                        #struct_name_in,
-                        #struct_name_out,
-                        MainLayout(#shader_stage),
-                        #graphic_shader_type
-                    ) }
-            }
+            #file_methods
+
+            /// Same lookup as the macro: `#include "file"` is looked up next to the
+            /// file that includes it, then in the include directories.
+            /// `#include <file>` only uses the include directories.
+            #[allow(dead_code)]
+            fn resolve_include(requested: &str, include_type: shaderc::IncludeType, requesting: &str, include_dirs: &[PathBuf])
+                -> Result<shaderc::ResolvedInclude, String> {
 
-            /// Reload the file and compile it to spirv again.
-            pub fn recompile(&mut self, device: Arc<Device>) -> Result<(), Box<std::error::Error>> {
-                let mut f = File::open(#path)?;
-                let mut content = String::new();
-                f.read_to_string(&mut content)?;
+                let mut candidates = vec![];
+                if include_type == shaderc::IncludeType::Relative {
+                    candidates.push(Path::new(requesting).parent().unwrap_or_else(|| Path::new("")).join(requested));
+                }
+                candidates.extend(include_dirs.iter().map(|dir| dir.join(requested)));
+
+                for candidate in candidates {
+                    if let Ok(content) = std::fs::read_to_string(&candidate) {
+                        return Ok(shaderc::ResolvedInclude {
+                            resolved_name: candidate.to_string_lossy().into_owned(),
+                            content,
+                        });
+                    }
+                }
 
-                let mut compiler = shaderc::Compiler::new().unwrap();
-                let spirv = compiler.compile_into_spirv(
-                    content.as_str(),
-                    shaderc::ShaderKind::#shaderc_type,
-                    #path, "main", None)?;
+                Err(format!("cannot find {} in the include directories", requested))
+            }
 
-                let spirv = spirv.as_binary();
+            #( #entry_points )*
 
-                //// then, change the module.
-                unsafe {
-                    self.module = ShaderModule::from_words(device, &spirv)?;
-                }
-                Ok(())
+            /// Reload the file and compile it to spirv again. Shaders written
+            /// inline in the macro cannot be recompiled and return an error.
+            pub fn recompile(&mut self, device: Arc<Device>) -> Result<(), Box<dyn std::error::Error>> {
+                #recompile_body
             }
         }
 
@@ -441,6 +1296,55 @@ pub fn twshader(input: TokenStream) -> TokenStream {
         );
 
 
-        expanded.into()
+        expanded
+
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camel_case_names() {
+        assert_eq!(camel_case("main"), "Main");
+        assert_eq!(camel_case("vs_main"), "VsMain");
+        assert_eq!(camel_case("vsMain"), "VsMain");
+        assert_eq!(camel_case("shadow-pass2"), "ShadowPass2");
+    }
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(snake_case("main"), "main");
+        assert_eq!(snake_case("vsMain"), "vs_main");
+        assert_eq!(snake_case("VsMain"), "vs_main");
+        assert_eq!(snake_case("fs__main"), "fs_main");
+        assert_eq!(snake_case("shadow-pass2"), "shadow_pass2");
+    }
+
+    fn parse_error(input: &str) -> String {
+        match syn::parse_str::<MacroInput>(input) {
+            Ok(_) => panic!("{} was parsed", input),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn compiler_keys_of_precompiled_modules() {
+        assert!(syn::parse_str::<MacroInput>(r#"path: "shader.spv", entry_point: "main""#).is_ok());
+        assert!(syn::parse_str::<MacroInput>(r#"path: "shader.frag", kind: "fragment", defines: { A: "1" }"#).is_ok());
+        assert_eq!(parse_error(r#"path: "shader.spv", defines: { A: "1" }"#), "`defines` cannot be used with a precompiled SPIR-V module");
+        assert_eq!(parse_error(r#"optimization: "performance", path: "shader.spv""#), "`optimization` cannot be used with a precompiled SPIR-V module");
+    }
+
+    #[test]
+    fn duplicate_descriptors() {
+        let input = |descriptors: &str| format!(r#"path: "shader.spv", descriptors: [{}]"#, descriptors);
+        let texture = "{ name: Texture, ty: SampledImage, set: 0, binding: 0 }";
+        assert!(syn::parse_str::<MacroInput>(&input(&format!("{}, {{ name: Normals, ty: SampledImage, set: 0, binding: 1 }}", texture))).is_ok());
+        assert_eq!(parse_error(&input(&format!("{}, {{ name: Texture, ty: SampledImage, set: 1, binding: 0 }}", texture))),
+                   "descriptor `Texture` is defined twice");
+        assert_eq!(parse_error(&input(&format!("{}, {{ name: Normals, ty: SampledImage, set: 0, binding: 0 }}", texture))),
+                   "descriptor Normals has the same set 0 and binding 0 as Texture");
+        assert_eq!(parse_error(&format!("{}, descriptors: []", input(texture))), "`descriptors` is defined twice");
+    }
 }
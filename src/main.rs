@@ -1,52 +1,100 @@
+// Every mode of the macro is expanded here so that a build checks them all.
+#![allow(dead_code)]
 
 mod vs {
     twgraph_shader::twshader!{
         path: "test.frag",
         kind: "fragment",
-        input: [
-            // This is the position in 2d space of the GUI
-            {
-                format: R32G32Sfloat,
-                name: "position",
-            },
-            // This is the texture coords
+        descriptors: [
             {
-                format: R32G32Sfloat,
-                name: "uv",
+                name: TexSampler,
+                ty: SampledImage,
+                binding: 0,
+                set: 1,
             },
-            // This is the color :)
             {
-                format: R32G32B32A32Sfloat,
-                name: "color"
+                name: Light,
+                ty: Buffer,
+                data: [(color, "vec3"), (position, "vec3")],
+                binding: 1,
+                set: 1,
             }
         ],
-        output: [
-
+    }
+}
 
-        ],
-        push_constants: {
-            name: PushConstants,
-            ranges: [(color, 4), (color2, 4)]
-        },
+// The shadow map only exists in the `shadowed` variant.
+mod lit {
+    twgraph_shader::twshader!{
+        path: "lit.frag",
+        kind: "fragment",
         descriptors: [
             {
-                name: UBO,
+                name: Light,
                 ty: Buffer,
-                data: [(model, "mat4"), (view, "mat4"), (proj, "mat4")],
                 binding: 0,
                 set: 0,
             },
             {
-                name: Texture,
-                ty: InputAttachment,
+                name: ShadowMap,
+                ty: SampledImage,
+                binding: 1,
+                set: 0,
+            }
+        ],
+        variants: [
+            { name: unshadowed },
+            { name: shadowed, defines: { SHADOWS: "1" } },
+        ],
+    }
+}
+
+mod particles {
+    twgraph_shader::twshader!{
+        path: "particles.comp",
+        kind: "compute",
+        descriptors: [
+            {
+                name: Particles,
+                ty: StorageBuffer,
                 binding: 0,
-                set: 2,
+                set: 0,
             }
-            
         ],
     }
 }
 
+mod normals {
+    twgraph_shader::twshader!{
+        path: "normals.geom",
+        kind: "geometry",
+    }
+}
+
+// Written in the macro, so the shader cannot be reloaded.
+mod fullscreen {
+    twgraph_shader::twshader!{
+        kind: "vertex",
+        src: r#"
+            #version 450
+
+            layout(location = 0) out vec2 v_tex_coords;
+
+            void main() {
+                v_tex_coords = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+                gl_Position = vec4(v_tex_coords * 2.0 - 1.0, 0.0, 1.0);
+            }
+        "#,
+    }
+}
+
+// Already compiled module with a vertex and a fragment entry point.
+mod passthrough {
+    twgraph_shader::twshader!{
+        path: "passthrough.spv",
+    }
+}
+
 fn main() {
     println!("hi");
 }
@@ -0,0 +1,154 @@
+//! Options of the GLSL compiler. The build done by the macro and the one done
+//! by `Shader::recompile` use the same options.
+
+use syn::{LitInt, LitStr};
+use syn::parse::{ParseStream, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Optimization {
+    None,
+    Size,
+    Performance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetEnv {
+    Vulkan1_0,
+    Vulkan1_1,
+}
+
+impl TargetEnv {
+
+    /// Version given to shaderc, in the format of `VK_MAKE_VERSION`.
+    fn version(&self) -> u32 {
+        match *self {
+            TargetEnv::Vulkan1_0 => 1 << 22,
+            TargetEnv::Vulkan1_1 => (1 << 22) | (1 << 12),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderOptions {
+    pub optimization: Optimization,
+    pub debug_info: bool,
+    // shaderc's defaults when not given.
+    pub target_env: Option<TargetEnv>,
+    pub glsl_version: Option<u32>,
+}
+
+impl ShaderOptions {
+
+    /// Debug info in debug builds and optimized shaders in release builds. The
+    /// profile is the one the macro is built with, which is the profile of the
+    /// crate using it unless `build-override` says otherwise.
+    pub fn new(optimization: Option<Optimization>, debug_info: Option<bool>, target_env: Option<TargetEnv>, glsl_version: Option<u32>) -> Self {
+        let release = !cfg!(debug_assertions);
+        ShaderOptions {
+            optimization: optimization.unwrap_or(if release { Optimization::Performance } else { Optimization::None }),
+            debug_info: debug_info.unwrap_or(!release),
+            target_env,
+            glsl_version,
+        }
+    }
+
+    /// Options of the build used for the reflection. The optimizer removes the
+    /// names of the variables and blocks when there is no debug info.
+    pub fn for_reflection(&self) -> Option<Self> {
+        if self.optimization == Optimization::None || self.debug_info {
+            return None;
+        }
+
+        Some(ShaderOptions {
+            optimization: Optimization::None,
+            debug_info: true,
+            ..*self
+        })
+    }
+
+    pub fn apply(&self, options: &mut shaderc::CompileOptions) {
+        match self.optimization {
+            Optimization::None => options.set_optimization_level(shaderc::OptimizationLevel::Zero),
+            Optimization::Size => options.set_optimization_level(shaderc::OptimizationLevel::Size),
+            Optimization::Performance => options.set_optimization_level(shaderc::OptimizationLevel::Performance),
+        }
+
+        if self.debug_info {
+            options.set_generate_debug_info();
+        }
+
+        if let Some(target_env) = self.target_env {
+            options.set_target_env(shaderc::TargetEnv::Vulkan, target_env.version());
+        }
+
+        if let Some(version) = self.glsl_version {
+            options.set_forced_version_profile(version, shaderc::GlslProfile::None);
+        }
+    }
+
+    /// Same as `apply`, for the generated `recompile`. The code uses a
+    /// `shaderc::CompileOptions` named `options`.
+    pub fn generate_apply(&self) -> proc_macro2::TokenStream {
+        let optimization = match self.optimization {
+            Optimization::None => quote!(Zero),
+            Optimization::Size => quote!(Size),
+            Optimization::Performance => quote!(Performance),
+        };
+
+        let debug_info = if self.debug_info {
+            quote!(options.set_generate_debug_info();)
+        } else {
+            quote!()
+        };
+
+        let target_env = match self.target_env {
+            Some(target_env) => {
+                let version = target_env.version();
+                quote!(options.set_target_env(shaderc::TargetEnv::Vulkan, #version);)
+            },
+            None => quote!(),
+        };
+
+        let glsl_version = match self.glsl_version {
+            Some(version) => quote!(options.set_forced_version_profile(#version, shaderc::GlslProfile::None);),
+            None => quote!(),
+        };
+
+        quote!(
+            options.set_optimization_level(shaderc::OptimizationLevel::#optimization);
+            #debug_info
+            #target_env
+            #glsl_version
+        )
+    }
+}
+
+/// Parse `"performance"`, `"size"` or `"none"`.
+pub fn parse_optimization(input: ParseStream) -> Result<Optimization> {
+    let value: LitStr = input.parse()?;
+    match value.value().as_ref() {
+        "none" => Ok(Optimization::None),
+        "size" => Ok(Optimization::Size),
+        "performance" => Ok(Optimization::Performance),
+        x => Err(syn::Error::new_spanned(&value, format!("unknown optimization `{}`, expected one of: none, size, performance", x))),
+    }
+}
+
+/// Parse `"vulkan1.0"` or `"vulkan1.1"`.
+pub fn parse_target_env(input: ParseStream) -> Result<TargetEnv> {
+    let value: LitStr = input.parse()?;
+    match value.value().as_ref() {
+        "vulkan1.0" => Ok(TargetEnv::Vulkan1_0),
+        "vulkan1.1" => Ok(TargetEnv::Vulkan1_1),
+        x => Err(syn::Error::new_spanned(&value, format!("unknown target environment `{}`, expected one of: vulkan1.0, vulkan1.1", x))),
+    }
+}
+
+/// Parse a GLSL version such as `450`.
+pub fn parse_glsl_version(input: ParseStream) -> Result<u32> {
+    let value: LitInt = input.parse()?;
+    match value.value() {
+        110 | 120 | 130 | 140 | 150 | 330 | 400 | 410 | 420 | 430 | 440 | 450 | 460 => Ok(value.value() as u32),
+        x => Err(syn::Error::new(value.span(), format!("unknown GLSL version `{}`, expected a version such as 450", x))),
+    }
+}
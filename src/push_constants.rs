@@ -1,10 +1,93 @@
-use syn::{Ident, Token, bracketed, braced, LitInt, parenthesized};
+use syn::{Ident, Token, bracketed, braced, Lit, LitInt, parenthesized};
 use syn::parse::{Parse, ParseStream, Result};
-
+use proc_macro2::Span;
+use std::collections::BTreeMap;
+use crate::{ShaderKind, Stages, duplicate_key, missing_key, unexpected_key};
+use crate::layout::{Field, FieldType, GlslType, Scalar, Std, check_block, generate_struct, reflect_struct, rust_ident, spirv_name, std_layout};
+use crate::spirv::{Spirv, Type, DECORATION_OFFSET, STORAGE_CLASS_PUSH_CONSTANT};
+
+
+/// Type of one field of the push constants.
+pub enum RangeType {
+    // Legacy description, a number of floats.
+    Floats(usize),
+    Glsl(GlslType),
+}
 
 pub struct PushConstants {
     pub name: Ident,
-    pub ranges: Vec<(Ident, usize)>,
+    // When not given, the fields are reflected from the shader.
+    pub ranges: Option<Vec<(Ident, RangeType)>>,
+    // All the stages by default.
+    pub stages: Option<Stages>,
+    // When the stages use different parts of the block. One range
+    // covering the whole block by default.
+    pub stage_ranges: Option<Vec<PcRange>>,
+}
+
+/// Part of the push constants used by some stages.
+pub struct PcRange {
+    offset: usize,
+    size: usize,
+    stages: Stages,
+    // The errors about the range point at it.
+    span: Span,
+}
+
+impl Parse for PcRange {
+
+    /// Parse for example `{ offset: 64, size: 16, stages: [fragment] }`
+    fn parse(input: ParseStream) -> Result<Self> {
+
+        let mut offset = None;
+        let mut size = None;
+        let mut stages = None;
+        let span = input.cursor().span();
+        let in_braces;
+        braced!(in_braces in input);
+
+        while !in_braces.is_empty() {
+
+            let ident: Ident = in_braces.parse()?;
+            in_braces.parse::<Token![:]>()?;
+
+            match ident.to_string().as_ref() {
+                "offset" => {
+                    if offset.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    offset = Some(in_braces.parse::<LitInt>()?.value() as usize);
+                }
+                "size" => {
+                    if size.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    size = Some(in_braces.parse::<LitInt>()?.value() as usize);
+                }
+                "stages" => {
+                    if stages.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    stages = Some(in_braces.parse::<Stages>()?);
+                }
+                _ => return Err(unexpected_key(&ident, &["offset", "size", "stages"])),
+            }
+
+            if !in_braces.is_empty() {
+                in_braces.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(PcRange {
+            offset: offset.ok_or_else(|| missing_key(span, "offset"))?,
+            size: size.ok_or_else(|| missing_key(span, "size"))?,
+            stages: stages.ok_or_else(|| missing_key(span, "stages"))?,
+            span,
+        })
+    }
 }
 
 impl Parse for PushConstants {
@@ -12,13 +95,29 @@ impl Parse for PushConstants {
     /// Parse for example:
     /// `{
     ///     name: MyStructName,
-    ///     ranges: [(color, 3), (scale: 2)],
+    ///     ranges: [(model, "mat4"), (material_id, "uint")],
+    ///     stages: [vertex, fragment],
     /// }`
     ///
+    /// Instead of `stages`, the stages can use different parts of the block:
+    /// `stage_ranges: [{ offset: 0, size: 64, stages: [vertex] }, { offset: 64, size: 16, stages: [fragment] }]`
+    /// A stage is in one range at most, and the range of each stage of the
+    /// shader holds the part of the block the shader declares.
+    ///
+    /// `ranges` can be omitted to get the fields from the shader. The old
+    /// `[(color, 3), (alpha, 1)]` form, which gives `[f32; N]` fields, is still
+    /// accepted when the block has the same packed floats, e.g.
+    /// `vec3 color; float alpha;`. Otherwise, for example with a `vec2` after
+    /// a `vec3`, which std430 aligns to 8 bytes, the types have to be given:
+    /// `[(color, "vec3"), (scale, "vec2")]`.
+    ///
     fn parse(input: ParseStream) -> Result<Self> {
 
         let mut name = None;
         let mut ranges = None;
+        let mut stages = None;
+        let mut stage_ranges = None;
+        let span = input.cursor().span();
         let in_braces;
         braced!(in_braces in input);
 
@@ -30,7 +129,7 @@ impl Parse for PushConstants {
             match ident.to_string().as_ref() {
                 "name" => {
                     if name.is_some() {
-                        panic!("Cannot parse 'name' twice");
+                        return Err(duplicate_key(&ident));
                     }
 
                     name = Some(in_braces.parse::<Ident>()?);
@@ -38,9 +137,9 @@ impl Parse for PushConstants {
                 "ranges" => {
 
                     if ranges.is_some() {
-                        panic!("Cannot parse 'ranges' twice");
+                        return Err(duplicate_key(&ident));
                     }
-                    // Parse [ (color, 4), (color2, 3)]
+                    // Parse [ (model, "mat4"), (material_id, "uint")] or [ (color, 4), (color2, 3)]
 
                     let mut ranges_vec = vec![];
                     let in_brackets;
@@ -54,21 +153,60 @@ impl Parse for PushConstants {
 
                         let range_ident: Ident = in_parens.parse()?;
                         in_parens.parse::<Token![,]>()?;
-                        let range_size: LitInt = in_parens.parse()?;
+                        let range_ty = match in_parens.parse::<Lit>()? {
+                            Lit::Int(size) => RangeType::Floats(size.value() as usize),
+                            Lit::Str(ty) => {
+                                let ty = GlslType::parse(&ty.value(), &[]).ok_or_else(|| {
+                                    syn::Error::new_spanned(&ty, format!("unsupported push constant type `{}`, expected a GLSL scalar, vector, matrix or array type", ty.value()))
+                                })?;
+                                RangeType::Glsl(ty)
+                            },
+                            lit => return Err(syn::Error::new_spanned(lit, "expected a number of floats or a GLSL type")),
+                        };
 
                         if !in_parens.is_empty() {
-                            panic!("Expected only tuple here");
+                            return Err(in_parens.error("expected only (name, type)"));
                         }
 
-                        ranges_vec.push((range_ident, range_size.value() as usize));
+                        ranges_vec.push((range_ident, range_ty));
                         if !in_brackets.is_empty() {
                             in_brackets.parse::<Token![,]>()?;
                         }
                     }
 
+                    let typed = ranges_vec.iter().filter(|(_, ty)| is_typed(ty)).count();
+                    if typed != 0 && typed != ranges_vec.len() {
+                        return Err(syn::Error::new_spanned(&ident, "cannot mix numbers of floats and GLSL types in `ranges`"));
+                    }
+
                     ranges = Some(ranges_vec);
                 }
-                _ => panic!("unexpected"),
+                "stages" => {
+                    if stages.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    stages = Some(in_braces.parse::<Stages>()?);
+                }
+                "stage_ranges" => {
+                    if stage_ranges.is_some() {
+                        return Err(duplicate_key(&ident));
+                    }
+
+                    let mut ranges_vec = vec![];
+                    let in_brackets;
+                    bracketed!(in_brackets in in_braces);
+
+                    while !in_brackets.is_empty() {
+                        ranges_vec.push(in_brackets.parse::<PcRange>()?);
+                        if !in_brackets.is_empty() {
+                            in_brackets.parse::<Token![,]>()?;
+                        }
+                    }
+
+                    stage_ranges = Some(ranges_vec);
+                }
+                _ => return Err(unexpected_key(&ident, &["name", "ranges", "stages", "stage_ranges"])),
             }
 
             if !in_braces.is_empty() {
@@ -76,59 +214,286 @@ impl Parse for PushConstants {
             }
         }
 
+        if stages.is_some() && stage_ranges.is_some() {
+            return Err(syn::Error::new(span, "cannot have both `stages` and `stage_ranges`"));
+        }
+
         Ok(PushConstants {
-            name: name.expect("Cannot find push constants name"),
-            ranges: ranges.expect("Cannot find push constants ranges"),
+            name: name.ok_or_else(|| missing_key(span, "name"))?,
+            ranges,
+            stages,
+            stage_ranges,
         })
 
     }
 }
 
-/// Return the pipeline layout and the data structure that represent this push constants.
-pub fn generate_pc(pc: Option<PushConstants>) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+/// Find the push constant block of the shader. Its struct is named after the
+/// block when there is no name in the macro input.
+fn find_push_constants(spirv: &Spirv) -> Option<(String, Type)> {
+    for id in spirv.variables() {
+        let (storage_class, type_id) = match spirv.variable(id) {
+            Some(var) => var,
+            None => continue,
+        };
+
+        if storage_class == STORAGE_CLASS_PUSH_CONSTANT {
+            return Some((spirv.name(type_id).to_owned(), spirv.resolve_type(type_id)));
+        }
+    }
+
+    None
+}
 
-    if let Some(pc) = pc {
-        let length = pc.ranges.len();
+/// The legacy fields are packed floats, so each one has to be placed like the
+/// member of the block at the same index, and hold as many floats.
+fn check_floats(spirv: &Spirv, block: &Type, name: &Ident, fields: &[Field]) -> Result<()> {
+    let (id, members) = match block {
+        Type::Struct { id, members } => (*id, members),
+        _ => return Err(syn::Error::new_spanned(name, format!("the block of {} is not a struct", name))),
+    };
+
+    if fields.len() != members.len() {
+        return Err(syn::Error::new_spanned(name, format!("{} has {} fields but the block in the shader has {} members",
+                                                         name, fields.len(), members.len())));
+    }
 
-        let mut inner_desc = vec![];
-        let mut offset = 0usize;
-        let mut struct_content = vec![];
-        for (_, (field, size)) in pc.ranges.iter().enumerate() {
-
-            let size_in_bytes = size * 4;
-            struct_content.push(quote!(
-                    pub #field: [f32; #size],
-                    ));
-            offset += size_in_bytes;
+    for (index, (field, member)) in fields.iter().zip(members.iter()).enumerate() {
+        let count = field.ty.size() / 4;
+        let spirv_offset = spirv.member_decoration(id, index as u32, DECORATION_OFFSET).map(|params| params[0] as usize);
+        if spirv_offset == Some(field.offset) && float_count(member) == Some(count) {
+            continue;
         }
 
-        let struct_name = pc.name;
-        let structure = quote!(
+        let place = match spirv_offset {
+            Some(spirv_offset) => format!("at offset {}", spirv_offset),
+            None => "without an offset".to_owned(),
+        };
+        let suggestion = match member {
+            Type::Struct { .. } => "give the fields their GLSL types".to_owned(),
+            _ => format!("use `({}, \"{}\")` instead", field.name, spirv_name(spirv, member)),
+        };
+        return Err(syn::Error::new_spanned(&field.name, format!(
+            "field {} of {} is {} packed floats at offset {} but the shader has {} {}, {}",
+            field.name, name, count, field.offset, spirv_name(spirv, member), place, suggestion)));
+    }
+
+    Ok(())
+}
+
+/// Number of floats of a member that is the same as packed floats.
+fn float_count(ty: &Type) -> Option<usize> {
+    let float = Type::Float { width: 32 };
+    match ty {
+        ty if *ty == float => Some(1),
+        Type::Vector { component, count } if **component == float => Some(*count as usize),
+        Type::Array { element, length, stride: Some(4) } if **element == float => Some(*length as usize),
+        _ => None,
+    }
+}
+
+/// An entry point can have its own push constants block, but before SPIR-V
+/// 1.4 the interface of an entry point does not list it. The block is only
+/// known when the module has one.
+pub fn check_single_block(spirv: &Spirv) -> std::result::Result<(), String> {
+    let count = spirv.variables().into_iter()
+        .filter(|id| spirv.variable(*id).map(|(storage_class, _)| storage_class) == Some(STORAGE_CLASS_PUSH_CONSTANT))
+        .count();
+    if count > 1 {
+        return Err(format!("the module has {} push constants blocks, only one is supported", count));
+    }
+
+    Ok(())
+}
 
-            #[repr(C)]
-            #[derive(Copy, Clone)]
-            pub struct #struct_name {
-                #( #struct_content)*
+/// Whether the shader has a push constants block.
+pub fn is_used(spirv: &Spirv) -> bool {
+    find_push_constants(spirv).is_some()
+}
+
+/// `used_by` is the shader, or all its variants.
+pub fn unused_error(pc: &PushConstants, used_by: &str) -> syn::Error {
+    syn::Error::new_spanned(&pc.name, format!("push constants {} are not used by {}", pc.name, used_by))
+}
+
+fn is_typed(ty: &RangeType) -> bool {
+    match ty {
+        RangeType::Glsl(_) => true,
+        RangeType::Floats(_) => false,
+    }
+}
+
+/// Vulkan only guarantees 128 bytes of push constants.
+const MAX_PUSH_CONSTANTS_SIZE: usize = 128;
+
+/// Sort the ranges given in the macro by offset and check that they are valid
+/// for Vulkan: a range starts and ends on 4 bytes, two ranges cannot overlap
+/// and they must fit in the guaranteed minimum. The block of the shader must
+/// be covered.
+fn check_ranges(name: &Ident, stage_ranges: &[PcRange], block_start: usize, block_size: usize, kinds: &[ShaderKind])
+                -> Result<Vec<(usize, usize, proc_macro2::TokenStream)>> {
+    let mut sorted: Vec<_> = stage_ranges.iter().collect();
+    sorted.sort_by_key(|r| r.offset);
+
+    let mut end = 0;
+    for range in sorted.iter() {
+        let (offset, size) = (range.offset, range.size);
+        if size == 0 || offset % 4 != 0 || size % 4 != 0 {
+            return Err(syn::Error::new(range.span, format!(
+                "push constant range {}..{} must be a non empty multiple of 4 bytes", offset, offset + size)));
+        }
+
+        if offset < end {
+            return Err(syn::Error::new(range.span, format!(
+                "push constant range {}..{} overlaps the range ending at {}", offset, offset + size, end)));
+        }
+
+        end = offset + size;
+        if end > MAX_PUSH_CONSTANTS_SIZE {
+            return Err(syn::Error::new(range.span, format!(
+                "push constant range {}..{} goes past the {} bytes guaranteed by Vulkan", offset, end, MAX_PUSH_CONSTANTS_SIZE)));
+        }
+    }
+
+    if block_size > end {
+        return Err(syn::Error::new_spanned(name, format!(
+            "the push constants block is {} bytes but the ranges end at {}", block_size, end)));
+    }
+
+    // Vulkan does not allow a stage in two ranges.
+    for (index, range) in stage_ranges.iter().enumerate() {
+        for stage in range.stages.0.iter() {
+            let previous = stage_ranges[..index].iter().find(|r| r.stages.0.iter().any(|s| s == stage));
+            if let Some(previous) = previous {
+                return Err(syn::Error::new_spanned(stage, format!(
+                    "stage {} is already in the push constant range {}..{}", stage, previous.offset, previous.offset + previous.size)));
             }
+        }
+    }
 
-        );
-        inner_desc.push(quote!(
+    // So each stage of the shader has a single range, which has to hold the
+    // part of the block the shader declares.
+    for kind in kinds.iter() {
+        let range = sorted.iter().find(|r| r.stages.contains(*kind));
+        match range {
+            Some(range) if range.offset <= block_start && range.offset + range.size >= block_size => (),
+            Some(range) => return Err(syn::Error::new(range.span, format!(
+                "push constant range {}..{} has the {} stage but does not hold bytes {}..{} of the block used by the shader",
+                range.offset, range.offset + range.size, kind.name(), block_start, block_size))),
+            None => return Err(syn::Error::new_spanned(name, format!(
+                "no push constant range has the {} stage of the shader", kind.name()))),
+        }
+    }
 
-                if num == 0 {
+    Ok(sorted.iter().map(|r| (r.offset, r.size, r.stages.generate_shaderstages())).collect())
+}
+
+/// Offset of the first member of the block, the shader does not use the
+/// bytes before it.
+fn block_start(spirv: &Spirv, block: &Type) -> usize {
+    match block {
+        Type::Struct { id, members } => (0..members.len() as u32)
+            .filter_map(|index| spirv.member_decoration(*id, index, DECORATION_OFFSET))
+            .map(|params| params[0] as usize)
+            .min()
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
 
-                    return Some(PipelineLayoutDescPcRange {
-                        offset: 0,
-                        size: #offset,
-                        stages: ShaderStages::all(),
-                    });
+/// Return the pipeline layout and the data structure that represent this push constants.
+/// The size of the range is the size of the block in the shader. With
+/// `allow_unused`, push constants that are not in the shader are skipped.
+pub fn generate_pc(pc: Option<&PushConstants>, spirv: &Spirv, kinds: &[ShaderKind], allow_unused: bool) -> Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+
+    if let Some((block_name, block_ty)) = find_push_constants(spirv) {
+
+        let mut structs = BTreeMap::new();
+        let size = match pc {
+            // Typed fields, laid out with the std430 rules like the block.
+            Some(PushConstants { ref name, ranges: Some(ref ranges), .. }) if ranges.iter().all(|(_, ty)| is_typed(ty)) => {
+                let members: Vec<_> = ranges.iter().filter_map(|(field, ty)| match ty {
+                    RangeType::Glsl(ty) => Some((field.clone(), ty.clone())),
+                    RangeType::Floats(_) => None,
+                }).collect();
+
+                check_block(spirv, &block_ty, name, &members, None, Std::Std430)?;
+                let (fields, struct_size) = std_layout(&members, Std::Std430);
+                structs.insert(name.to_string(), generate_struct(name, &fields, struct_size));
+
+                reflect_struct(spirv, &block_ty, name, Std::Std430, &mut BTreeMap::new())?
+            },
+            // Legacy description, one [f32; N] per range. The floats are packed,
+            // so the fields have to be placed like the members of the block.
+            Some(PushConstants { ref name, ranges: Some(ref ranges), .. }) => {
+                let mut fields = vec![];
+                let mut offset = 0;
+                for (field, ty) in ranges.iter() {
+                    if let RangeType::Floats(count) = ty {
+                        let ty = FieldType::Array { element: Box::new(FieldType::Scalar(Scalar::Float)), length: *count, stride: 4 };
+                        let size = ty.size();
+                        fields.push(Field { name: field.clone(), ty, offset });
+                        offset += size;
+                    }
                 }
 
-        ));
+                check_floats(spirv, &block_ty, name, &fields)?;
+
+                // vulkano copies the size of the range out of the struct.
+                let size = reflect_struct(spirv, &block_ty, name, Std::Std430, &mut BTreeMap::new())?;
+                if offset != size {
+                    return Err(syn::Error::new_spanned(name, format!(
+                        "{} is {} bytes but the push constants block in the shader is {} bytes", name, offset, size)));
+                }
+
+                structs.insert(name.to_string(), generate_struct(name, &fields, offset));
+                size
+            },
+            Some(PushConstants { ref name, ranges: None, .. }) => {
+                reflect_struct(spirv, &block_ty, name, Std::Std430, &mut structs)?
+            },
+            None => {
+                let name = rust_ident(&block_name, || "PushConstants".to_owned());
+                reflect_struct(spirv, &block_ty, &name, Std::Std430, &mut structs)?
+            },
+        };
+
+        let structures = structs.values();
+        let structure = quote!(
+            #( #structures )*
+
+            /// Size in bytes of the push constants block in the shader.
+            pub const PUSH_CONSTANTS_SIZE: usize = #size;
+        );
+
+        // Only the ranges given in the macro are limited to 128 bytes, the
+        // default range is the size of the block.
+        let pc_ranges = match pc {
+            Some(PushConstants { ref name, stage_ranges: Some(ref stage_ranges), .. }) => check_ranges(name, stage_ranges, block_start(spirv, &block_ty), size, kinds)?,
+            Some(PushConstants { stages: Some(ref stages), .. }) => vec![(0, size, stages.generate_shaderstages())],
+            _ => vec![(0, size, quote!(ShaderStages::all()))],
+        };
+
+        let mut inner_desc = vec![];
+        for (num, (offset, size, stages)) in pc_ranges.iter().enumerate() {
+            inner_desc.push(quote!(
+
+                    if num == #num {
+
+                        return Some(PipelineLayoutDescPcRange {
+                            offset: #offset,
+                            size: #size,
+                            stages: #stages,
+                        });
+                    }
 
+            ));
+        }
 
-        (quote!(
+        let num_ranges = pc_ranges.len();
+        Ok((quote!(
                 // Number of push constants ranges (think: number of push constants).
-                fn num_push_constants_ranges(&self) -> usize { 1 }
+                fn num_push_constants_ranges(&self) -> usize { #num_ranges }
                 // Each push constant range in memory.
                 fn push_constants_range(&self, num: usize) -> Option<PipelineLayoutDescPcRange> { 
 
@@ -137,18 +502,138 @@ pub fn generate_pc(pc: Option<PushConstants>) -> (proc_macro2::TokenStream, proc
 
                     None
                 }
-        ), structure)
+        ), structure))
     } else {
 
-        (quote!(
+        if let Some(pc) = pc.filter(|_| !allow_unused) {
+            return Err(unused_error(pc, "the shader"));
+        }
+
+        Ok((quote!(
                 // Number of push constants ranges (think: number of push constants).
                 fn num_push_constants_ranges(&self) -> usize { 0 }
                 // Each push constant range in memory.
                 fn push_constants_range(&self, num: usize) -> Option<PipelineLayoutDescPcRange> { 
                     None
                 }
-        ), quote!())
+        ), quote!()))
+    }
+
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spirv::Instruction;
+
+    fn range(offset: usize, size: usize, stages: &[&str]) -> PcRange {
+        let stages = stages.iter().map(|stage| Ident::new(stage, Span::call_site())).collect();
+        PcRange { offset, size, stages: Stages(stages), span: Span::call_site() }
+    }
+
+    fn check(ranges: &[PcRange], block_start: usize, block_size: usize, kinds: &[ShaderKind]) -> std::result::Result<usize, String> {
+        let name = Ident::new("Constants", Span::call_site());
+        check_ranges(&name, ranges, block_start, block_size, kinds).map(|ranges| ranges.len()).map_err(|e| e.to_string())
+    }
+
+    // One push constants block per entry point of the module.
+    fn blocks(count: u32) -> Spirv {
+        let mut instructions = vec![];
+        for index in 0..count {
+            instructions.push(Instruction::TypePointer { result_id: 10 + index, storage_class: STORAGE_CLASS_PUSH_CONSTANT, type_id: 1 });
+            instructions.push(Instruction::Variable { result_type_id: 10 + index, result_id: 20 + index });
+        }
+        Spirv { instructions }
+    }
+
+    #[test]
+    fn single_block() {
+        assert_eq!(check_single_block(&blocks(1)), Ok(()));
+        assert_eq!(check_single_block(&blocks(2)), Err("the module has 2 push constants blocks, only one is supported".to_owned()));
+    }
+
+    // `layout(push_constant) uniform Constants { ... }` with the given members and offsets.
+    fn block(members: Vec<Type>, offsets: &[u32]) -> (Spirv, Type) {
+        let instructions = offsets.iter().enumerate().map(|(index, offset)| {
+            Instruction::MemberDecorate { target_id: 20, member: index as u32, decoration: DECORATION_OFFSET, params: vec![*offset] }
+        }).collect();
+        (Spirv { instructions }, Type::Struct { id: 20, members })
     }
 
+    fn vector(count: u32) -> Type {
+        Type::Vector { component: Box::new(Type::Float { width: 32 }), count }
+    }
+
+    fn check_packed(spirv: &Spirv, block: &Type, counts: &[(&str, usize)]) -> std::result::Result<(), String> {
+        let mut fields = vec![];
+        let mut offset = 0;
+        for (name, count) in counts {
+            let ty = FieldType::Array { element: Box::new(FieldType::Scalar(Scalar::Float)), length: *count, stride: 4 };
+            fields.push(Field { name: Ident::new(name, Span::call_site()), ty, offset });
+            offset += count * 4;
+        }
+        check_floats(spirv, block, &Ident::new("Constants", Span::call_site()), &fields).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn packed_floats() {
+        // vec3 color; float alpha;
+        let (spirv, block) = block(vec![vector(3), Type::Float { width: 32 }], &[0, 12]);
+        assert_eq!(check_packed(&spirv, &block, &[("color", 3), ("alpha", 1)]), Ok(()));
+        assert_eq!(check_packed(&spirv, &block, &[("color", 2), ("alpha", 2)]),
+                   Err("field color of Constants is 2 packed floats at offset 0 but the shader has vec3 at offset 0, use `(color, \"vec3\")` instead".to_owned()));
+    }
+
+    #[test]
+    fn aligned_floats() {
+        // vec3 color; vec2 scale;
+        let (spirv, block) = block(vec![vector(3), vector(2)], &[0, 16]);
+        assert_eq!(check_packed(&spirv, &block, &[("color", 3), ("scale", 2)]),
+                   Err("field scale of Constants is 2 packed floats at offset 12 but the shader has vec2 at offset 16, use `(scale, \"vec2\")` instead".to_owned()));
+    }
+
+    #[test]
+    fn ranges_of_two_stages() {
+        let ranges = [range(16, 16, &["fragment"]), range(0, 16, &["vertex"])];
+        assert_eq!(check(&ranges, 16, 32, &[ShaderKind::Fragment]), Ok(2));
+        assert_eq!(check(&ranges, 0, 16, &[ShaderKind::Vertex]), Ok(2));
+    }
 
+    #[test]
+    fn overlapping_ranges() {
+        let ranges = [range(0, 16, &["vertex"]), range(8, 16, &["fragment"])];
+        assert_eq!(check(&ranges, 0, 16, &[ShaderKind::Vertex]),
+                   Err("push constant range 8..24 overlaps the range ending at 16".to_owned()));
+    }
+
+    #[test]
+    fn range_past_the_limit() {
+        let ranges = [range(0, 132, &["vertex"])];
+        assert_eq!(check(&ranges, 0, 132, &[ShaderKind::Vertex]),
+                   Err("push constant range 0..132 goes past the 128 bytes guaranteed by Vulkan".to_owned()));
+    }
+
+    #[test]
+    fn unaligned_range() {
+        let ranges = [range(2, 16, &["vertex"])];
+        assert_eq!(check(&ranges, 0, 16, &[ShaderKind::Vertex]),
+                   Err("push constant range 2..18 must be a non empty multiple of 4 bytes".to_owned()));
+    }
+
+    #[test]
+    fn stage_in_two_ranges() {
+        let ranges = [range(0, 16, &["vertex"]), range(16, 16, &["fragment", "vertex"])];
+        assert_eq!(check(&ranges, 0, 32, &[ShaderKind::Vertex]),
+                   Err("stage vertex is already in the push constant range 0..16".to_owned()));
+    }
+
+    #[test]
+    fn stage_of_the_shader() {
+        let ranges = [range(0, 16, &["vertex"]), range(16, 16, &["fragment"])];
+        assert_eq!(check(&ranges, 0, 16, &[ShaderKind::Compute]),
+                   Err("no push constant range has the compute stage of the shader".to_owned()));
+        assert_eq!(check(&ranges, 0, 32, &[ShaderKind::Fragment]),
+                   Err("push constant range 16..32 has the fragment stage but does not hold bytes 0..32 of the block used by the shader".to_owned()));
+    }
 }
@@ -0,0 +1,737 @@
+//! Tiny SPIR-V parser. Only decodes the instructions we need to get
+//! information about the compiled shader, the rest is ignored.
+
+use std::collections::HashSet;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_NAME: u16 = 5;
+const OP_MEMBER_NAME: u16 = 6;
+const OP_ENTRY_POINT: u16 = 15;
+const OP_EXECUTION_MODE: u16 = 16;
+const OP_TYPE_BOOL: u16 = 20;
+const OP_TYPE_INT: u16 = 21;
+const OP_TYPE_FLOAT: u16 = 22;
+const OP_TYPE_VECTOR: u16 = 23;
+const OP_TYPE_MATRIX: u16 = 24;
+const OP_TYPE_IMAGE: u16 = 25;
+const OP_TYPE_SAMPLER: u16 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+const OP_TYPE_ARRAY: u16 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u16 = 29;
+const OP_TYPE_STRUCT: u16 = 30;
+const OP_TYPE_POINTER: u16 = 32;
+const OP_CONSTANT: u16 = 43;
+const OP_VARIABLE: u16 = 59;
+const OP_DECORATE: u16 = 71;
+const OP_MEMBER_DECORATE: u16 = 72;
+
+// Execution models
+pub const EXECUTION_MODEL_VERTEX: u32 = 0;
+pub const EXECUTION_MODEL_TESSELLATION_CONTROL: u32 = 1;
+pub const EXECUTION_MODEL_TESSELLATION_EVALUATION: u32 = 2;
+pub const EXECUTION_MODEL_GEOMETRY: u32 = 3;
+pub const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+pub const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+
+// Storage classes
+pub const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+pub const STORAGE_CLASS_INPUT: u32 = 1;
+pub const STORAGE_CLASS_UNIFORM: u32 = 2;
+pub const STORAGE_CLASS_OUTPUT: u32 = 3;
+pub const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+pub const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+// Decorations
+pub const DECORATION_BUFFER_BLOCK: u32 = 3;
+pub const DECORATION_ROW_MAJOR: u32 = 4;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+pub const DECORATION_MATRIX_STRIDE: u32 = 7;
+pub const DECORATION_BUILTIN: u32 = 11;
+pub const DECORATION_PATCH: u32 = 15;
+pub const DECORATION_NON_WRITABLE: u32 = 24;
+pub const DECORATION_LOCATION: u32 = 30;
+pub const DECORATION_BINDING: u32 = 33;
+pub const DECORATION_DESCRIPTOR_SET: u32 = 34;
+pub const DECORATION_OFFSET: u32 = 35;
+
+// Image dimensions
+pub const DIM_1D: u32 = 0;
+pub const DIM_2D: u32 = 1;
+pub const DIM_3D: u32 = 2;
+pub const DIM_CUBE: u32 = 3;
+pub const DIM_BUFFER: u32 = 5;
+pub const DIM_SUBPASS_DATA: u32 = 6;
+
+// Images are used with a sampler (1) or as storage images (2).
+pub const IMAGE_SAMPLED_STORAGE: u32 = 2;
+
+// Execution modes
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_INPUT_POINTS: u32 = 19;
+const EXECUTION_MODE_INPUT_LINES: u32 = 20;
+const EXECUTION_MODE_INPUT_LINES_ADJACENCY: u32 = 21;
+const EXECUTION_MODE_TRIANGLES: u32 = 22;
+const EXECUTION_MODE_INPUT_TRIANGLES_ADJACENCY: u32 = 23;
+
+/// Input primitive of a geometry shader.
+#[derive(Debug, Clone, Copy)]
+pub enum GeometryInput {
+    Points,
+    Lines,
+    LinesWithAdjacency,
+    Triangles,
+    TrianglesWithAdjacency,
+}
+
+pub enum Instruction {
+    Name {
+        target_id: u32,
+        name: String,
+    },
+    MemberName {
+        target_id: u32,
+        member: u32,
+        name: String,
+    },
+    EntryPoint {
+        execution_model: u32,
+        id: u32,
+        name: String,
+        interface: Vec<u32>,
+    },
+    ExecutionMode {
+        target_id: u32,
+        mode: u32,
+        params: Vec<u32>,
+    },
+    TypeBool {
+        result_id: u32,
+    },
+    TypeInt {
+        result_id: u32,
+        width: u32,
+        signed: bool,
+    },
+    TypeFloat {
+        result_id: u32,
+        width: u32,
+    },
+    TypeVector {
+        result_id: u32,
+        component_id: u32,
+        count: u32,
+    },
+    TypeMatrix {
+        result_id: u32,
+        column_type_id: u32,
+        column_count: u32,
+    },
+    TypeImage {
+        result_id: u32,
+        dim: u32,
+        arrayed: bool,
+        multisampled: bool,
+        sampled: u32,
+        format: u32,
+    },
+    TypeSampler {
+        result_id: u32,
+    },
+    TypeSampledImage {
+        result_id: u32,
+        image_type_id: u32,
+    },
+    TypeArray {
+        result_id: u32,
+        type_id: u32,
+        length_id: u32,
+    },
+    TypeRuntimeArray {
+        result_id: u32,
+        type_id: u32,
+    },
+    TypeStruct {
+        result_id: u32,
+        member_types: Vec<u32>,
+    },
+    TypePointer {
+        result_id: u32,
+        storage_class: u32,
+        type_id: u32,
+    },
+    Constant {
+        result_id: u32,
+        data: Vec<u32>,
+    },
+    Variable {
+        result_type_id: u32,
+        result_id: u32,
+    },
+    Decorate {
+        target_id: u32,
+        decoration: u32,
+        params: Vec<u32>,
+    },
+    MemberDecorate {
+        target_id: u32,
+        member: u32,
+        decoration: u32,
+        params: Vec<u32>,
+    },
+    Unknown,
+}
+
+/// A type resolved from the type instructions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: Box<Type>, count: u32 },
+    Matrix { column: Box<Type>, count: u32 },
+    Image { dim: u32, arrayed: bool, multisampled: bool, sampled: u32, format: u32 },
+    Sampler,
+    SampledImage { image: Box<Type> },
+    // The stride is only known for arrays inside a block.
+    Array { element: Box<Type>, length: u32, stride: Option<u32> },
+    RuntimeArray { element: Box<Type>, stride: Option<u32> },
+    Struct { id: u32, members: Vec<Type> },
+    // A type this parser does not decode, or an id that is not a type.
+    Unsupported { id: u32 },
+}
+
+pub struct Spirv {
+    pub instructions: Vec<Instruction>,
+}
+
+impl Spirv {
+
+    /// Parse the words output by shaderc or read from a `.spv` file. A
+    /// truncated or corrupt module is an error.
+    pub fn parse(words: &[u32]) -> Result<Self, String> {
+        if words.len() < 5 || words[0] != MAGIC_NUMBER {
+            return Err("the module does not start with a SPIR-V header".to_owned());
+        }
+
+        // First 5 words are the header. (magic, version, generator, bound, schema)
+        let mut instructions = vec![];
+        let mut rest = &words[5..];
+        while !rest.is_empty() {
+            let word_count = (rest[0] >> 16) as usize;
+            let opcode = (rest[0] & 0xffff) as u16;
+            if word_count == 0 || word_count > rest.len() {
+                return Err(format!("malformed SPIR-V instruction at word {}", words.len() - rest.len()));
+            }
+
+            let operands = &rest[1..word_count];
+            if operands.len() < min_operands(opcode) {
+                return Err(format!("SPIR-V instruction {} at word {} has {} operands, expected at least {}",
+                                   opcode, words.len() - rest.len(), operands.len(), min_operands(opcode)));
+            }
+
+            let instruction = decode_instruction(opcode, operands);
+            match &instruction {
+                Instruction::Decorate { decoration, params, .. } | Instruction::MemberDecorate { decoration, params, .. }
+                    if params.is_empty() && has_literal(*decoration) => {
+                    return Err(format!("SPIR-V decoration {} at word {} does not have its value", decoration, words.len() - rest.len()));
+                },
+                _ => (),
+            }
+
+            instructions.push(instruction);
+            rest = &rest[word_count..];
+        }
+
+        // Types are declared before they are used, so resolving a type cannot
+        // loop forever.
+        let types: HashSet<u32> = instructions.iter().filter_map(type_result_id).collect();
+        let mut declared = HashSet::new();
+        for instruction in instructions.iter() {
+            if let Some(used) = type_references(instruction).into_iter().find(|id| types.contains(id) && !declared.contains(id)) {
+                return Err(format!("SPIR-V type {} is used before it is declared", used));
+            }
+
+            declared.extend(type_result_id(instruction));
+        }
+
+        Ok(Spirv { instructions })
+    }
+
+    /// Name and execution model of the entry points, in the order of the module.
+    pub fn entry_points(&self) -> Vec<(String, u32)> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::EntryPoint { execution_model, name, .. } => Some((name.clone(), *execution_model)),
+            _ => None,
+        }).collect()
+    }
+
+    /// Ids of the variables used by an entry point.
+    pub fn entry_point_interface(&self, entry_name: &str) -> &[u32] {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::EntryPoint { name, interface, .. } if name == entry_name => Some(interface.as_slice()),
+            _ => None,
+        }).next()
+        .expect(&format!("Cannot find entry point {}", entry_name))
+    }
+
+    /// Id of the entry point with the given name.
+    pub fn entry_point_id(&self, entry_name: &str) -> Option<u32> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::EntryPoint { id, name, .. } if name == entry_name => Some(*id),
+            _ => None,
+        }).next()
+    }
+
+    /// Parameters of the given execution mode for an entry point, if it is set.
+    pub fn execution_mode(&self, entry_id: u32, wanted_mode: u32) -> Option<&[u32]> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::ExecutionMode { target_id, mode, params } if *target_id == entry_id && *mode == wanted_mode => {
+                Some(params.as_slice())
+            },
+            _ => None,
+        }).next()
+    }
+
+    /// Name given by `OpName` to an id. Empty if the shader was compiled
+    /// without debug names.
+    pub fn name(&self, id: u32) -> &str {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::Name { target_id, name } if *target_id == id => Some(name.as_str()),
+            _ => None,
+        }).next()
+        .unwrap_or("")
+    }
+
+    /// Name of a member of a struct.
+    pub fn member_name(&self, id: u32, wanted_member: u32) -> &str {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::MemberName { target_id, member, name } if *target_id == id && *member == wanted_member => {
+                Some(name.as_str())
+            },
+            _ => None,
+        }).next()
+        .unwrap_or("")
+    }
+
+    /// Parameters of a decoration applied to a member of a struct.
+    pub fn member_decoration(&self, id: u32, wanted_member: u32, wanted: u32) -> Option<&[u32]> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::MemberDecorate { target_id, member, decoration, params }
+                if *target_id == id && *member == wanted_member && *decoration == wanted => {
+                Some(params.as_slice())
+            },
+            _ => None,
+        }).next()
+    }
+
+    /// Parameters of a decoration applied to an id, if it is there.
+    pub fn decoration(&self, id: u32, wanted: u32) -> Option<&[u32]> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::Decorate { target_id, decoration, params } if *target_id == id && *decoration == wanted => {
+                Some(params.as_slice())
+            },
+            _ => None,
+        }).next()
+    }
+
+    /// Ids of all the global variables.
+    pub fn variables(&self) -> Vec<u32> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::Variable { result_id, .. } => Some(*result_id),
+            _ => None,
+        }).collect()
+    }
+
+    /// Storage class and pointed type of a variable.
+    pub fn variable(&self, id: u32) -> Option<(u32, u32)> {
+        let pointer_id = self.instructions.iter().filter_map(|i| match i {
+            Instruction::Variable { result_type_id, result_id, .. } if *result_id == id => Some(*result_type_id),
+            _ => None,
+        }).next()?;
+
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::TypePointer { result_id, storage_class, type_id } if *result_id == pointer_id => {
+                Some((*storage_class, *type_id))
+            },
+            _ => None,
+        }).next()
+    }
+
+    /// Value of a 32 bits integer constant.
+    pub fn constant_u32(&self, id: u32) -> Option<u32> {
+        self.instructions.iter().filter_map(|i| match i {
+            Instruction::Constant { result_id, data } if *result_id == id => Some(data[0]),
+            _ => None,
+        }).next()
+    }
+
+    /// Follow the type instructions to build the full type of an id.
+    pub fn resolve_type(&self, id: u32) -> Type {
+        for instruction in self.instructions.iter() {
+            match instruction {
+                Instruction::TypeBool { result_id } if *result_id == id => return Type::Bool,
+                Instruction::TypeInt { result_id, width, signed } if *result_id == id => {
+                    return Type::Int { width: *width, signed: *signed };
+                },
+                Instruction::TypeFloat { result_id, width } if *result_id == id => {
+                    return Type::Float { width: *width };
+                },
+                Instruction::TypeVector { result_id, component_id, count } if *result_id == id => {
+                    return Type::Vector {
+                        component: Box::new(self.resolve_type(*component_id)),
+                        count: *count,
+                    };
+                },
+                Instruction::TypeMatrix { result_id, column_type_id, column_count } if *result_id == id => {
+                    return Type::Matrix {
+                        column: Box::new(self.resolve_type(*column_type_id)),
+                        count: *column_count,
+                    };
+                },
+                Instruction::TypeImage { result_id, dim, arrayed, multisampled, sampled, format } if *result_id == id => {
+                    return Type::Image {
+                        dim: *dim,
+                        arrayed: *arrayed,
+                        multisampled: *multisampled,
+                        sampled: *sampled,
+                        format: *format,
+                    };
+                },
+                Instruction::TypeSampler { result_id } if *result_id == id => return Type::Sampler,
+                Instruction::TypeSampledImage { result_id, image_type_id } if *result_id == id => {
+                    return Type::SampledImage { image: Box::new(self.resolve_type(*image_type_id)) };
+                },
+                Instruction::TypeArray { result_id, type_id, length_id } if *result_id == id => {
+                    let length = match self.constant_u32(*length_id) {
+                        Some(length) => length,
+                        None => return Type::Unsupported { id },
+                    };
+
+                    return Type::Array {
+                        element: Box::new(self.resolve_type(*type_id)),
+                        length,
+                        stride: self.decoration(id, DECORATION_ARRAY_STRIDE).map(|p| p[0]),
+                    };
+                },
+                Instruction::TypeRuntimeArray { result_id, type_id } if *result_id == id => {
+                    return Type::RuntimeArray {
+                        element: Box::new(self.resolve_type(*type_id)),
+                        stride: self.decoration(id, DECORATION_ARRAY_STRIDE).map(|p| p[0]),
+                    };
+                },
+                Instruction::TypeStruct { result_id, member_types } if *result_id == id => {
+                    return Type::Struct {
+                        id,
+                        members: member_types.iter().map(|t| self.resolve_type(*t)).collect(),
+                    };
+                },
+                _ => (),
+            }
+        }
+
+        Type::Unsupported { id }
+    }
+
+    /// Workgroup size of a compute entry point. GLSL defaults to 1 when
+    /// `local_size_*` is not specified.
+    pub fn local_size(&self, entry_name: &str) -> [u32; 3] {
+        let entry_id = self.entry_point_id(entry_name)
+            .expect(&format!("Cannot find entry point {}", entry_name));
+
+        match self.execution_mode(entry_id, EXECUTION_MODE_LOCAL_SIZE) {
+            Some(params) if params.len() == 3 => [params[0], params[1], params[2]],
+            _ => [1, 1, 1],
+        }
+    }
+
+    /// Input primitive declared with `layout(points) in;` and friends in a
+    /// geometry shader.
+    pub fn geometry_input(&self, entry_name: &str) -> Option<GeometryInput> {
+        let entry_id = self.entry_point_id(entry_name)
+            .expect(&format!("Cannot find entry point {}", entry_name));
+
+        let modes = [
+            (EXECUTION_MODE_INPUT_POINTS, GeometryInput::Points),
+            (EXECUTION_MODE_INPUT_LINES, GeometryInput::Lines),
+            (EXECUTION_MODE_INPUT_LINES_ADJACENCY, GeometryInput::LinesWithAdjacency),
+            (EXECUTION_MODE_TRIANGLES, GeometryInput::Triangles),
+            (EXECUTION_MODE_INPUT_TRIANGLES_ADJACENCY, GeometryInput::TrianglesWithAdjacency),
+        ];
+
+        for (mode, input) in modes.iter() {
+            if self.execution_mode(entry_id, *mode).is_some() {
+                return Some(*input);
+            }
+        }
+
+        None
+    }
+}
+
+/// Id declared by a type instruction.
+fn type_result_id(instruction: &Instruction) -> Option<u32> {
+    match instruction {
+        Instruction::TypeBool { result_id }
+            | Instruction::TypeInt { result_id, .. }
+            | Instruction::TypeFloat { result_id, .. }
+            | Instruction::TypeVector { result_id, .. }
+            | Instruction::TypeMatrix { result_id, .. }
+            | Instruction::TypeImage { result_id, .. }
+            | Instruction::TypeSampler { result_id }
+            | Instruction::TypeSampledImage { result_id, .. }
+            | Instruction::TypeArray { result_id, .. }
+            | Instruction::TypeRuntimeArray { result_id, .. }
+            | Instruction::TypeStruct { result_id, .. } => Some(*result_id),
+        _ => None,
+    }
+}
+
+/// Types a type instruction is made of.
+fn type_references(instruction: &Instruction) -> Vec<u32> {
+    match instruction {
+        Instruction::TypeVector { component_id, .. } => vec![*component_id],
+        Instruction::TypeMatrix { column_type_id, .. } => vec![*column_type_id],
+        Instruction::TypeSampledImage { image_type_id, .. } => vec![*image_type_id],
+        Instruction::TypeArray { type_id, .. } | Instruction::TypeRuntimeArray { type_id, .. } => vec![*type_id],
+        Instruction::TypeStruct { member_types, .. } => member_types.clone(),
+        _ => vec![],
+    }
+}
+
+/// Number of operands `decode_instruction` reads, the strings count for one word.
+fn min_operands(opcode: u16) -> usize {
+    match opcode {
+        OP_TYPE_BOOL | OP_TYPE_SAMPLER | OP_TYPE_STRUCT => 1,
+        OP_NAME | OP_EXECUTION_MODE | OP_TYPE_FLOAT | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_RUNTIME_ARRAY | OP_DECORATE => 2,
+        OP_MEMBER_NAME | OP_ENTRY_POINT | OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY | OP_TYPE_POINTER
+            | OP_CONSTANT | OP_VARIABLE | OP_MEMBER_DECORATE => 3,
+        OP_TYPE_IMAGE => 8,
+        _ => 0,
+    }
+}
+
+/// Decorations with a literal value, which the reflection reads.
+fn has_literal(decoration: u32) -> bool {
+    match decoration {
+        DECORATION_ARRAY_STRIDE | DECORATION_MATRIX_STRIDE | DECORATION_BUILTIN | DECORATION_LOCATION
+            | DECORATION_BINDING | DECORATION_DESCRIPTOR_SET | DECORATION_OFFSET => true,
+        _ => false,
+    }
+}
+
+/// The operands are at least `min_operands(opcode)` words.
+fn decode_instruction(opcode: u16, operands: &[u32]) -> Instruction {
+    match opcode {
+        OP_NAME => Instruction::Name {
+            target_id: operands[0],
+            name: parse_string(&operands[1..]).0,
+        },
+        OP_MEMBER_NAME => Instruction::MemberName {
+            target_id: operands[0],
+            member: operands[1],
+            name: parse_string(&operands[2..]).0,
+        },
+        OP_ENTRY_POINT => {
+            let (name, consumed) = parse_string(&operands[2..]);
+            Instruction::EntryPoint {
+                execution_model: operands[0],
+                id: operands[1],
+                name,
+                interface: operands[2 + consumed..].to_vec(),
+            }
+        },
+        OP_EXECUTION_MODE => Instruction::ExecutionMode {
+            target_id: operands[0],
+            mode: operands[1],
+            params: operands[2..].to_vec(),
+        },
+        OP_TYPE_BOOL => Instruction::TypeBool { result_id: operands[0] },
+        OP_TYPE_INT => Instruction::TypeInt {
+            result_id: operands[0],
+            width: operands[1],
+            signed: operands[2] != 0,
+        },
+        OP_TYPE_FLOAT => Instruction::TypeFloat {
+            result_id: operands[0],
+            width: operands[1],
+        },
+        OP_TYPE_VECTOR => Instruction::TypeVector {
+            result_id: operands[0],
+            component_id: operands[1],
+            count: operands[2],
+        },
+        OP_TYPE_MATRIX => Instruction::TypeMatrix {
+            result_id: operands[0],
+            column_type_id: operands[1],
+            column_count: operands[2],
+        },
+        OP_TYPE_IMAGE => Instruction::TypeImage {
+            result_id: operands[0],
+            dim: operands[2],
+            arrayed: operands[4] != 0,
+            multisampled: operands[5] != 0,
+            sampled: operands[6],
+            format: operands[7],
+        },
+        OP_TYPE_SAMPLER => Instruction::TypeSampler { result_id: operands[0] },
+        OP_TYPE_SAMPLED_IMAGE => Instruction::TypeSampledImage {
+            result_id: operands[0],
+            image_type_id: operands[1],
+        },
+        OP_TYPE_ARRAY => Instruction::TypeArray {
+            result_id: operands[0],
+            type_id: operands[1],
+            length_id: operands[2],
+        },
+        OP_TYPE_RUNTIME_ARRAY => Instruction::TypeRuntimeArray {
+            result_id: operands[0],
+            type_id: operands[1],
+        },
+        OP_TYPE_STRUCT => Instruction::TypeStruct {
+            result_id: operands[0],
+            member_types: operands[1..].to_vec(),
+        },
+        OP_TYPE_POINTER => Instruction::TypePointer {
+            result_id: operands[0],
+            storage_class: operands[1],
+            type_id: operands[2],
+        },
+        OP_CONSTANT => Instruction::Constant {
+            result_id: operands[1],
+            data: operands[2..].to_vec(),
+        },
+        OP_VARIABLE => Instruction::Variable {
+            result_type_id: operands[0],
+            result_id: operands[1],
+        },
+        OP_DECORATE => Instruction::Decorate {
+            target_id: operands[0],
+            decoration: operands[1],
+            params: operands[2..].to_vec(),
+        },
+        OP_MEMBER_DECORATE => Instruction::MemberDecorate {
+            target_id: operands[0],
+            member: operands[1],
+            decoration: operands[2],
+            params: operands[3..].to_vec(),
+        },
+        _ => Instruction::Unknown,
+    }
+}
+
+/// Literal strings are nul-terminated and packed in little-endian words.
+/// Returns the string and the number of words it used.
+fn parse_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = vec![];
+    for (index, word) in words.iter().enumerate() {
+        for shift in 0..4 {
+            let byte = ((word >> (shift * 8)) & 0xff) as u8;
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), index + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instruction(opcode: u16, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![(operands.len() as u32 + 1) << 16 | u32::from(opcode)];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(value: &str) -> Vec<u32> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        while bytes.len() % 4 != 0 {
+            bytes.push(0);
+        }
+
+        bytes.chunks(4).map(|b| u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16 | u32::from(b[3]) << 24).collect()
+    }
+
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        for instruction in instructions {
+            words.extend_from_slice(instruction);
+        }
+        words
+    }
+
+    // layout(location = 0) in vec4 position[2]; in a vertex shader `main`.
+    fn vertex_module() -> Vec<u32> {
+        let main = [&[EXECUTION_MODEL_VERTEX, 1][..], &string("main"), &[10]].concat();
+        module(&[
+            instruction(OP_ENTRY_POINT, &main),
+            instruction(OP_NAME, &[&[10][..], &string("position")].concat()),
+            instruction(OP_DECORATE, &[10, DECORATION_LOCATION, 0]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+            instruction(OP_TYPE_VECTOR, &[3, 2, 4]),
+            instruction(OP_TYPE_INT, &[4, 32, 0]),
+            instruction(OP_CONSTANT, &[4, 5, 2]),
+            instruction(OP_TYPE_ARRAY, &[6, 3, 5]),
+            instruction(OP_TYPE_POINTER, &[7, STORAGE_CLASS_INPUT, 6]),
+            instruction(OP_VARIABLE, &[7, 10, STORAGE_CLASS_INPUT]),
+        ])
+    }
+
+    #[test]
+    fn parse_module() {
+        let spirv = Spirv::parse(&vertex_module()).unwrap();
+        assert_eq!(spirv.entry_points(), vec![("main".to_owned(), EXECUTION_MODEL_VERTEX)]);
+        assert_eq!(spirv.entry_point_interface("main"), &[10]);
+        assert_eq!(spirv.name(10), "position");
+        assert_eq!(spirv.name(2), "");
+        assert_eq!(spirv.decoration(10, DECORATION_LOCATION), Some(&[0][..]));
+        assert_eq!(spirv.variable(10), Some((STORAGE_CLASS_INPUT, 6)));
+
+        let vec4 = Type::Vector { component: Box::new(Type::Float { width: 32 }), count: 4 };
+        assert_eq!(spirv.resolve_type(6), Type::Array { element: Box::new(vec4), length: 2, stride: None });
+        assert_eq!(spirv.resolve_type(42), Type::Unsupported { id: 42 });
+    }
+
+    #[test]
+    fn parse_byte_strings() {
+        assert_eq!(parse_string(&string("main")), ("main".to_owned(), 2));
+        assert_eq!(parse_string(&string("abc")), ("abc".to_owned(), 1));
+    }
+
+    #[test]
+    fn reject_bad_header() {
+        assert!(Spirv::parse(&[]).is_err());
+        assert!(Spirv::parse(&[0x0302_2307, 0x0001_0000, 0, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn reject_truncated_module() {
+        let words = vertex_module();
+        assert!(Spirv::parse(&words[..words.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn reject_missing_operands() {
+        assert!(Spirv::parse(&module(&[instruction(OP_TYPE_VECTOR, &[3, 2])])).is_err());
+        assert!(Spirv::parse(&module(&[instruction(OP_DECORATE, &[10, DECORATION_LOCATION])])).is_err());
+        assert!(Spirv::parse(&module(&[instruction(OP_DECORATE, &[10, DECORATION_BUFFER_BLOCK])])).is_ok());
+    }
+
+    #[test]
+    fn reject_type_used_before_declared() {
+        let words = module(&[
+            instruction(OP_TYPE_STRUCT, &[3, 2]),
+            instruction(OP_TYPE_FLOAT, &[2, 32]),
+        ]);
+        assert!(Spirv::parse(&words).is_err());
+
+        // A struct containing itself would make `resolve_type` loop forever.
+        assert!(Spirv::parse(&module(&[instruction(OP_TYPE_STRUCT, &[3, 3])])).is_err());
+    }
+}